@@ -343,6 +343,13 @@ impl DeepSizeOf for num::BigInt {
     }
 }
 
+impl DeepSizeOf for num::BigRational {
+    fn deep_size_of_children(&self, context: &mut Context) -> usize {
+        self.numer().deep_size_of_children(context) +
+        self.denom().deep_size_of_children(context)
+    }
+}
+
 impl DeepSizeOf for lsp_types::Url {
     fn deep_size_of_children(&self, _: &mut Context) -> usize {
         // this is an underestimate, but Url doesn't expose its capacity
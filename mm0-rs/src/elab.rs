@@ -17,6 +17,7 @@ pub mod math_parser;
 pub mod local_context;
 pub mod refine;
 pub mod proof;
+#[cfg(test)] mod tests;
 
 use std::ops::{Deref, DerefMut};
 use std::mem;
@@ -24,7 +25,7 @@ use std::result::Result as StdResult;
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 use std::time::{Instant, Duration};
 use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{future::Future, pin::Pin, task::{Context, Poll}};
 use futures::channel::oneshot::{Receiver, channel};
 use lsp_types::{Diagnostic, DiagnosticRelatedInformation, Location};
@@ -32,6 +33,7 @@ use environment::*;
 use environment::Literal as ELiteral;
 use lisp::LispVal;
 use spans::Spans;
+use local_context::PreparedStmt;
 pub use {environment::Environment, local_context::LocalContext};
 pub use crate::parser::ErrorLevel;
 pub use frozen::{FrozenEnv, FrozenLispKind, FrozenLispVal, FrozenAtomData};
@@ -130,6 +132,11 @@ impl ElabError {
     ElabError { pos: pos.into(), level: ErrorLevel::Warning, kind: ElabErrorKind::Boxed(e.into(), None)}
   }
 
+  /// Make an elaboration warning from a position, a message, and a list of related info.
+  pub fn warn_with_info(pos: impl Into<Span>, msg: BoxError, v: Vec<(FileSpan, BoxError)>) -> ElabError {
+    ElabError { pos: pos.into(), level: ErrorLevel::Warning, kind: ElabErrorKind::Boxed(msg, Some(v))}
+  }
+
   /// Make an info message at a position
   pub fn info(pos: impl Into<Span>, e: impl Into<BoxError>) -> ElabError {
     ElabError { pos: pos.into(), level: ErrorLevel::Info, kind: ElabErrorKind::Boxed(e.into(), None)}
@@ -162,7 +169,7 @@ impl From<ParseError> for ElabError {
 /// Records the current reporting setting. A report that is suppressed by the reporting mode
 /// will not appear in the error list / as a diagnostic, but a fatal error will still prevent
 /// proof export.
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 struct ReportMode {
   /// Do we report on errors?
   error: bool,
@@ -218,6 +225,47 @@ pub struct Elaborator {
   check_proofs: bool,
   /// The current reporting mode, whether we will report each severity of error
   reporting: ReportMode,
+  /// True if calls to tactic builtins are currently being recorded into `recorded`
+  recording: bool,
+  /// The most recent list of recorded tactic calls, as `(f args...)` lists
+  recorded: Vec<LispVal>,
+  /// The state of the pseudo-random number generator used by `random`
+  rng: u64,
+  /// The theorems that have been marked deprecated using `deprecate`, with an optional
+  /// message to display when they are used
+  deprecated: HashMap<ThmID, Option<ArcString>>,
+  /// The predicate set by `set-name-policy`, which is called on the name of each new
+  /// declaration, or `#undef` if there is no naming policy
+  name_policy: LispVal,
+  /// The prefix set by `with-namespace`, which is added to the names of
+  /// declarations created by `add-term!` and `add-thm!`
+  namespace: Option<ArcString>,
+  /// The prefixes opened by `open-namespace`, most recent last, which are used
+  /// to resolve names that are not otherwise defined
+  open_namespaces: Vec<ArcString>,
+  /// An index from term constructors to the theorems whose conclusion is an application
+  /// of that term, used by `thms-by-head`. The `usize` is the number of theorems that
+  /// have been indexed so far; later theorems are added the next time it is queried.
+  thm_heads: (usize, HashMap<TermID, Vec<ThmID>>),
+  /// The environments of the files imported so far by `import` statements,
+  /// keyed by canonical path, which are available to `import-env`
+  imported: HashMap<PathBuf, FrozenEnv>,
+  /// The proofs of theorems that have been materialized by `get-decl`, so that repeated
+  /// calls do not rebuild the proof term. Proofs are immutable once added, so entries
+  /// never need to be invalidated, but the cache is emptied when it reaches
+  /// `PROOF_CACHE_SIZE` entries so that it does not hold every proof in the file.
+  proof_cache: HashMap<ThmID, LispVal>,
+  /// The statements elaborated by `prepare-stmt`, keyed by the handle returned to
+  /// the user. The `usize` is the next handle to hand out; handles are never reused, so
+  /// a handle whose statement was dropped by a `with-env-snapshot` rollback stays invalid
+  /// instead of referring to a later statement. Statements are kept until the end of the file.
+  prepared: (usize, HashMap<usize, PreparedStmt>),
+  /// If deduplication of reports is enabled by `set-report-dedup`, the set of
+  /// `(span, level, message)` triples reported so far in the current statement;
+  /// later reports that are identical to one of these are dropped.
+  seen_reports: Option<HashSet<(Span, ErrorLevel, String)>>,
+  /// The name of the declaration currently being elaborated, if any, for `current-decl`
+  cur_decl: Option<AtomID>,
 }
 
 impl Deref for Elaborator {
@@ -255,6 +303,19 @@ impl Elaborator {
       mm0_mode,
       check_proofs: true,
       reporting: ReportMode::new(),
+      recording: false,
+      recorded: vec![],
+      rng: 0,
+      deprecated: HashMap::new(),
+      name_policy: LispVal::undef(),
+      namespace: None,
+      open_namespaces: vec![],
+      thm_heads: (0, HashMap::new()),
+      imported: HashMap::new(),
+      proof_cache: HashMap::new(),
+      prepared: (0, HashMap::new()),
+      seen_reports: None,
+      cur_decl: None,
     }
   }
 
@@ -267,10 +328,48 @@ impl Elaborator {
   pub fn fspan(&self, span: Span) -> FileSpan { FileSpan {file: self.path.clone(), span} }
 
   fn report(&mut self, e: ElabError) {
-    if self.reporting.active(e.level) {self.errors.push(e)}
+    if !self.reporting.active(e.level) {return}
+    if let Some(seen) = &mut self.seen_reports {
+      if !seen.insert((e.pos, e.level, e.kind.msg())) {return}
+    }
+    self.errors.push(e)
   }
   fn catch(&mut self, r: Result<()>) { r.unwrap_or_else(|e| self.report(e)) }
 
+  /// Add the current namespace prefix (if any) to the name of a new declaration.
+  fn namespaced(&mut self, x: AtomID) -> AtomID {
+    match &self.namespace {
+      None => x,
+      Some(ns) => {
+        let s = format!("{}{}", ns, self.data[x].name);
+        self.get_atom(&s)
+      }
+    }
+  }
+
+  /// Resolve an otherwise undefined name `a` against the namespaces opened by
+  /// `open-namespace`, most recent first. `f` is called on each prefixed name that exists,
+  /// and the first `Some` result is returned.
+  fn resolve_open<T>(&self, a: AtomID, f: impl Fn(AtomID, &AtomData) -> Option<T>) -> Option<T> {
+    self.open_namespaces.iter().rev().find_map(|ns| {
+      let b = *self.atoms.get(&*format!("{}{}", ns, self.data[a].name))?;
+      f(b, &self.data[b])
+    })
+  }
+
+  /// Check the name of a new declaration against the naming policy,
+  /// and report a warning if the policy rejects it.
+  fn check_name_policy(&mut self, sp: Span, a: AtomID) -> Result<()> {
+    if self.name_policy.is_def() {
+      let f = self.name_policy.clone();
+      if !self.call_func(sp, f, vec![LispVal::atom(a)])?.truthy() {
+        self.report(ElabError::warn(sp,
+          format!("'{}' does not satisfy the naming policy", self.data[a].name)))
+      }
+    }
+    Ok(())
+  }
+
   fn push_spans(&mut self) {
     self.env.spans.push(mem::take(&mut self.spans));
   }
@@ -289,37 +388,50 @@ impl Elaborator {
     let term = self.term(a).ok_or_else(|| ElabError::new_e(n.id, "term not declared"))?;
     self.spans.insert(n.id, ObjectKind::Term(term, n.id));
     let tk: ArcString = self.span(n.c.trim).into();
-    let (rassoc, nargs, lits) = match n.k {
-      SimpleNotaKind::Prefix => {
+    let infix = match n.k {
+      SimpleNotaKind::Prefix => None,
+      SimpleNotaKind::Infix {right} => Some(right),
+    };
+    let (rassoc, nargs, lits) = self.simple_nota_lits(n.id, term, &tk, n.prec, infix)?;
+    self.add_const(n.c.trim, n.prec)?;
+    let info = NotaInfo { span: self.fspan(n.id), term, nargs, rassoc: Some(rassoc), lits };
+    match n.k {
+      SimpleNotaKind::Prefix => self.pe.add_prefix(tk.clone(), info),
+      SimpleNotaKind::Infix {..} => self.pe.add_infix(tk.clone(), info),
+    }.map_err(|r| ElabError::with_info(n.id,
+      format!("constant '{}' already declared", tk).into(),
+      vec![(r.decl1, "declared here".into())]))
+  }
+
+  /// Construct the notation literals for a `prefix` (if `infix` is `None`) or
+  /// `infixl/r` (if `infix` is `Some(right)`) declaration of constant `tk` for `term`
+  /// at precedence `prec`. Returns the associativity, the number of arguments,
+  /// and the literals, for use in a [`NotaInfo`].
+  fn simple_nota_lits(&self, sp: Span, term: TermID, tk: &ArcString,
+      prec: Prec, infix: Option<bool>) -> Result<(bool, usize, Vec<ELiteral>)> {
+    match infix {
+      None => {
         let nargs = self.terms[term].args.len();
         let mut lits = Vec::with_capacity(nargs);
         if let Some(m) = nargs.checked_sub(1) {
           for i in 0..m {lits.push(ELiteral::Var(i, Prec::Max))};
-          lits.push(ELiteral::Var(m, n.prec));
+          lits.push(ELiteral::Var(m, prec));
         }
-        (true, nargs, lits)
+        Ok((true, nargs, lits))
       }
-      SimpleNotaKind::Infix {right} =>
-        if let Prec::Prec(i) = n.prec {
-          let i2 = i.checked_add(1).ok_or_else(|| ElabError::new_e(n.id, "precedence out of range"))?;
+      Some(right) =>
+        if let Prec::Prec(i) = prec {
+          let i2 = i.checked_add(1).ok_or_else(|| ElabError::new_e(sp, "precedence out of range"))?;
           let (l, r) = if right {(i2, i)} else {(i, i2)};
-          self.check_term_nargs(n.id, term, 2)?;
-          (right, 2, vec![
+          self.check_term_nargs(sp, term, 2)?;
+          Ok((right, 2, vec![
             ELiteral::Var(0, Prec::Prec(l)),
             ELiteral::Const(tk.clone()),
-            ELiteral::Var(1, Prec::Prec(r))])
+            ELiteral::Var(1, Prec::Prec(r))]))
         } else {
-          return Err(ElabError::new_e(n.id, "max prec not allowed for infix"))
+          Err(ElabError::new_e(sp, "max prec not allowed for infix"))
         }
-    };
-    self.add_const(n.c.trim, n.prec)?;
-    let info = NotaInfo { span: self.fspan(n.id), term, nargs, rassoc: Some(rassoc), lits };
-    match n.k {
-      SimpleNotaKind::Prefix => self.pe.add_prefix(tk.clone(), info),
-      SimpleNotaKind::Infix {..} => self.pe.add_infix(tk.clone(), info),
-    }.map_err(|r| ElabError::with_info(n.id,
-      format!("constant '{}' already declared", tk).into(),
-      vec![(r.decl1, "declared here".into())]))
+    }
   }
 
   fn elab_coe(&mut self, id: Span, from: Span, to: Span) -> Result<()> {
@@ -334,15 +446,26 @@ impl Elaborator {
     self.spans.insert(from, ObjectKind::Sort(s1));
     self.spans.insert(to, ObjectKind::Sort(s2));
     let fsp = self.fspan(id);
-    self.add_coe(s1, s2, fsp, t)
+    if let Some(w) = self.add_coe(s1, s2, fsp, t)? { self.report(w) }
+    Ok(())
   }
 
   fn add_const(&mut self, tk: Span, p: Prec) -> Result<()> {
-    let s = self.span(tk).into();
+    let s: ArcString = self.span(tk).into();
     let fsp = self.fspan(tk);
-    self.pe.add_const(s, fsp, p).map_err(|r| ElabError::with_info(tk,
-      "constant already declared with a different precedence".into(),
-      vec![(r.decl1, "declared here".into())]))
+    self.add_const_at(tk, s, fsp, p)
+  }
+
+  /// Add the constant `s` at precedence `p`, declared at `fsp`, reporting
+  /// a precedence conflict at `tk`.
+  fn add_const_at(&mut self, tk: Span, s: ArcString, fsp: FileSpan, p: Prec) -> Result<()> {
+    self.pe.add_const(s.clone(), fsp, p).map_err(|r| {
+      let p1 = self.pe.consts[&*s].1;
+      ElabError::with_info(tk,
+        format!("constant '{}' declared with precedence {}, \
+          but it was already declared with precedence {}", s, p, p1).into(),
+        vec![(r.decl1, format!("declared with precedence {} here", p1).into())])
+    })
   }
 
   fn elab_gen_nota(&mut self, nota: &GenNota) -> Result<()> {
@@ -471,14 +594,22 @@ impl Elaborator {
   fn elab_stmt(&mut self, stmt: &Stmt, span: Span) -> Result<ElabStmt> {
     self.cur_timeout = self.timeout.and_then(|d| Instant::now().checked_add(d));
     self.spans.set_stmt(span);
+    if let Some(seen) = &mut self.seen_reports {seen.clear()}
     match &stmt.k {
       &StmtKind::Sort(sp, sd) => {
         let a = self.env.get_atom(self.ast.span(sp));
         let fsp = self.fspan(sp);
         let id = self.add_sort(a, fsp, span, sd).map_err(|e| e.into_elab_error(sp))?;
         self.spans.insert(sp, ObjectKind::Sort(id));
+        self.check_name_policy(sp, a)?;
+      }
+      StmtKind::Decl(d) => {
+        let a = self.env.get_atom(self.ast.span(d.id));
+        let old = mem::replace(&mut self.cur_decl, Some(a));
+        let r = self.elab_decl(span, d);
+        self.cur_decl = old;
+        r?
       }
-      StmtKind::Decl(d) => self.elab_decl(span, d)?,
       StmtKind::Delimiter(Delimiter::Both(f)) => self.pe.add_delimiters(f, f),
       StmtKind::Delimiter(Delimiter::LeftRight(ls, rs)) => self.pe.add_delimiters(ls, rs),
       StmtKind::SimpleNota(n) => self.elab_simple_nota(n)?,
@@ -555,7 +686,7 @@ pub fn elaborate<T>(
 
   enum UnfinishedStmt<T> {
     None,
-    Import(Span, Receiver<(T, FrozenEnv)>),
+    Import(Span, Option<FileRef>, Receiver<(T, FrozenEnv)>),
   }
 
   struct ElabFutureInner<T> {
@@ -577,11 +708,14 @@ pub fn elaborate<T>(
       'l: loop {
         match progress {
           UnfinishedStmt::None => {},
-          UnfinishedStmt::Import(sp, other) => {
+          UnfinishedStmt::Import(sp, file, other) => {
             if let Ok((t, env)) = ready!(unsafe { Pin::new_unchecked(other) }.poll(cx)) {
               toks.push(t);
               let r = elab.env.merge(&env, *sp, &mut elab.errors);
               elab.catch(r);
+              if let Some(file) = file.take() {
+                elab.imported.insert(file.path().clone(), env);
+              }
             }
             *idx += 1;
           }
@@ -593,10 +727,10 @@ pub fn elaborate<T>(
             Ok(ElabStmt::Ok) => {}
             Ok(ElabStmt::Import(sp)) => {
               let (file, recv) = recv.remove(&sp).unwrap();
-              if let Some(file) = file {
-                elab.spans.insert(sp, ObjectKind::Import(file));
+              if let Some(file) = &file {
+                elab.spans.insert(sp, ObjectKind::Import(file.clone()));
               }
-              *progress = UnfinishedStmt::Import(sp, recv);
+              *progress = UnfinishedStmt::Import(sp, file, recv);
               elab.push_spans();
               continue 'l
             }
@@ -141,6 +141,17 @@ pub enum ExprNode {
   App(TermID, Vec<ExprNode>),
 }
 
+impl ExprNode {
+  /// Call `f` on every term constructor that appears in this expression.
+  /// (Terms may be visited more than once.)
+  pub fn visit_terms(&self, f: &mut impl FnMut(TermID)) {
+    if let ExprNode::App(t, ref es) = *self {
+      f(t);
+      for e in es {e.visit_terms(f)}
+    }
+  }
+}
+
 /// The `Expr` type stores expression dags using a local context of expression nodes
 /// and a final expression. See [`ExprNode`] for explanation of the variants.
 ///
@@ -253,6 +264,30 @@ impl ProofNode {
       }
     }
   }
+
+  /// Call `f` on every term constructor and theorem that appears in this proof.
+  /// (Declarations may be visited more than once.)
+  pub fn visit_decls(&self, f: &mut impl FnMut(DeclKey)) {
+    match self {
+      ProofNode::Ref(_) | ProofNode::Dummy(_, _) => {}
+      ProofNode::Hyp(_, e) | ProofNode::Refl(e) | ProofNode::Sym(e) => e.visit_decls(f),
+      &ProofNode::Term {term, ref args} | &ProofNode::Cong {term, ref args} => {
+        f(DeclKey::Term(term));
+        for e in &**args {e.visit_decls(f)}
+      }
+      &ProofNode::Thm {thm, ref args, ref res} => {
+        f(DeclKey::Thm(thm));
+        for e in &**args {e.visit_decls(f)}
+        res.visit_decls(f)
+      }
+      ProofNode::Conv(p) => {p.0.visit_decls(f); p.1.visit_decls(f); p.2.visit_decls(f)}
+      &ProofNode::Unfold {term, ref args, ref res} => {
+        f(DeclKey::Term(term));
+        for e in &**args {e.visit_decls(f)}
+        res.0.visit_decls(f); res.1.visit_decls(f); res.2.visit_decls(f)
+      }
+    }
+  }
 }
 
 impl From<&ExprNode> for ProofNode {
@@ -326,6 +361,33 @@ pub struct Thm {
   pub proof: Option<Option<Proof>>,
 }
 
+impl Term {
+  /// Call `f` on every term constructor that appears in the definition of this term,
+  /// if it has one.
+  pub fn visit_deps(&self, f: &mut impl FnMut(TermID)) {
+    if let Some(Some(e)) = &self.val {
+      for e in &e.heap {e.visit_terms(f)}
+      e.head.visit_terms(f)
+    }
+  }
+}
+
+impl Thm {
+  /// Call `f` on every term constructor and theorem that appears in the statement
+  /// or the proof of this theorem.
+  pub fn visit_deps(&self, f: &mut impl FnMut(DeclKey)) {
+    let mut g = |t| f(DeclKey::Term(t));
+    for e in &self.heap {e.visit_terms(&mut g)}
+    for (_, e) in &self.hyps {e.visit_terms(&mut g)}
+    self.ret.visit_terms(&mut g);
+    if let Some(Some(p)) = &self.proof {
+      for e in &p.heap {e.visit_decls(f)}
+      for e in &p.hyps {e.visit_decls(f)}
+      p.head.visit_decls(f)
+    }
+  }
+}
+
 /// A global order on sorts, declarations ([`Term`] and [`Thm`]), and lisp
 /// global definitions based on declaration order.
 ///
@@ -430,6 +492,15 @@ impl Coe {
     write!(s, "{}", sorts[s1].name)?;
     self.write_arrows_r(sorts, s, related, s1, s2)
   }
+
+  /// Push the sequence of term constructors that make up this coercion onto `out`,
+  /// in order of application (so the first element is applied to the input).
+  pub fn terms(&self, out: &mut Vec<TermID>) {
+    match *self {
+      Coe::One(_, t) => out.push(t),
+      Coe::Trans(ref c1, _, ref c2) => {c1.terms(out); c2.terms(out)}
+    }
+  }
 }
 
 /// The (non-logical) data used by the dynamic parser to interpret formulas.
@@ -513,6 +584,10 @@ pub enum ObjectKind {
   Proof(FrozenLispVal),
   /// This is an import; hovering does nothing and go-to-definition goes to the file
   Import(FileRef),
+  /// This is a span annotated by `annotate-span`; hovering yields the given text
+  Custom(ArcString),
+  /// This is a span annotated by `annotate-goto`; go-to-definition goes to the given location
+  Goto(FileSpan),
 }
 
 impl ObjectKind {
@@ -549,6 +624,25 @@ pub struct Environment {
   pub spans: Vec<Spans<ObjectKind>>,
 }
 
+/// The extent of an `Environment` at some point in time, produced by
+/// [`Environment::snapshot`] and consumed by [`Environment::rollback`].
+///
+/// Declarations are only ever appended, so they are recorded by length, but the
+/// notations and coercions are stored in hash maps, so the snapshot holds a full copy
+/// of the `ParserEnv`. Taking a snapshot therefore costs time and memory proportional
+/// to the amount of notation in the environment.
+///
+/// [`Environment::snapshot`]: struct.Environment.html#method.snapshot
+/// [`Environment::rollback`]: struct.Environment.html#method.rollback
+#[derive(Debug)]
+pub struct EnvSnapshot {
+  sorts: usize,
+  terms: usize,
+  thms: usize,
+  stmts: usize,
+  pe: ParserEnv,
+}
+
 macro_rules! make_atoms {
   {consts $n:expr;} => {};
   {consts $n:expr; $(#[$attr:meta])* $x:ident $doc0:expr, $($xs:tt)*} => {
@@ -610,15 +704,40 @@ make_atoms! {
   /// In elaborated proofs, `(:unfold t es c)` is a proof of definitional unfolding.
   /// (The initial colon avoids name collision with MM0 theorems, which don't allow `:` in identifiers.)
   UNFOLD: ":unfold",
+  /// In elaborated proofs, `(:refl e)` is a proof of reflexivity. This is the same as `e`,
+  /// but it makes the intent explicit when building conversions from lisp.
+  REFL: ":refl",
+  /// In elaborated proofs, `(:cong t c1 ... cn)` is a proof of congruence. This is the same
+  /// as `(t c1 ... cn)`, but it is a conversion even if all the `ci` are reflexivity proofs.
+  CONG: ":cong",
   /// In MMU proofs, `(:let h p1 p2)` is a let-binding for supporting deduplication.
   LET: ":let",
   /// In refine, `{p : t}` is a type ascription for proofs.
   COLON: ":",
   /// In refine, `?` is a proof by "sorry" (stubbing the proof without immediate error)
   QMARK: "?",
+  /// In `refine`, `(refine :tgt e p)` elaborates `p` against the expression `e`
+  /// instead of the first goal.
+  TGT: ":tgt",
   /// The `refine-extra-args` function is a callback used when an application in refine
   /// uses too many arguments.
   REFINE_EXTRA_ARGS: "refine-extra-args",
+  /// `sort` is an atom used by `get-sort` and `get-stmts` to report sort declarations
+  SORT: "sort",
+  /// `decl` is an atom used by `get-stmts` to report term and theorem declarations
+  DECL: "decl",
+  /// `global` is an atom used by `get-stmts` to report global lisp definitions
+  GLOBAL: "global",
+  /// `max` is an atom used by `get-notation` to report the maximum precedence
+  MAX: "max",
+  /// `pure` is an atom used by `get-sort` to report the sort modifier
+  PURE: "pure",
+  /// `strict` is an atom used by `get-sort` to report the sort modifier
+  STRICT: "strict",
+  /// `provable` is an atom used by `get-sort` to report the sort modifier
+  PROVABLE: "provable",
+  /// `free` is an atom used by `get-sort` to report the sort modifier
+  FREE: "free",
   /// `term` is an atom used by `add-decl` to add a term/def declaration
   TERM: "term",
   /// `def` is an atom used by `add-decl` to add a term/def declaration
@@ -641,6 +760,28 @@ make_atoms! {
   WARN: "warn",
   /// `info` is an error level recognized by `set-reporting`
   INFO: "info",
+  /// `left` is an atom used by `get-prec-assoc` to report a left associative precedence level
+  LEFT: "left",
+  /// `right` is an atom used by `get-prec-assoc` to report a right associative precedence level
+  RIGHT: "right",
+  /// `thm` is an atom used by `declared?` to report axiom and theorem declarations
+  THM_DECL: "thm",
+  /// `:unchecked` is an atom used by `add-thm!` to skip checking the proof of a theorem
+  UNCHECKED: ":unchecked",
+  /// `rollback` is an atom returned to `with-env-snapshot` to discard the new declarations
+  ROLLBACK: "rollback",
+  /// `var` is an atom used by `get-spans` to report local variables
+  VAR: "var",
+  /// `expr` is an atom used by `get-spans` to report expressions
+  EXPR: "expr",
+  /// `proof` is an atom used by `get-spans` to report proofs
+  PROOF: "proof",
+  /// `import` is an atom used by `get-spans` to report imports
+  IMPORT: "import",
+  /// `custom` is an atom used by `get-spans` to report hover text added by `annotate-span`
+  CUSTOM: "custom",
+  /// `goto` is an atom used by `get-spans` to report go-to-definition targets added by `annotate-goto`
+  GOTO: "goto",
 }
 
 /// An implementation of a map `u8 -> bool` using a 32 byte array as a bitset.
@@ -851,9 +992,9 @@ impl Remap<Remapper> for Coe {
 /// two definitions. This keeps just the locations of those definitions.
 #[derive(Debug)]
 pub struct IncompatibleError {
-  /// The first declaration in the conflict.
+  /// The first declaration in the conflict, which is the one already in the environment.
   pub decl1: FileSpan,
-  /// The second declaration in the conflict.
+  /// The second declaration in the conflict, which is the one that was rejected.
   pub decl2: FileSpan,
 }
 
@@ -886,8 +1027,7 @@ impl ParserEnv {
   pub fn add_prec_assoc(&mut self, p: u32, sp: FileSpan, r: bool) -> Result<(), IncompatibleError> {
     if let Some((_, e)) = self.prec_assoc.try_insert(p, (sp.clone(), r)) {
       if e.get().1 == r { return Ok(()) }
-      let (decl1, decl2) = if r { (e.get().0.clone(), sp) } else { (sp, e.get().0.clone()) };
-      Err(IncompatibleError {decl1, decl2})
+      Err(IncompatibleError { decl1: e.get().0.clone(), decl2: sp })
     } else { Ok(()) }
   }
 
@@ -972,12 +1112,23 @@ impl ParserEnv {
   /// Add a `coercion t: s1 > s2;` declaration to the parser.
   ///
   /// This function can fail if the updated coercion graph contains a diamond or cycle.
+  /// On success, it returns a warning if `t` also has `prefix` or `infix` notation,
+  /// since the coercion is then ambiguous with the explicit notation when parsing.
   pub fn add_coe(&mut self, sp: Span, sorts: &SortVec<Sort>,
-      s1: SortID, s2: SortID, fsp: FileSpan, t: TermID) -> Result<(), ElabError> {
+      s1: SortID, s2: SortID, fsp: FileSpan, t: TermID) -> Result<Option<ElabError>, ElabError> {
     self.add_coe_raw(sp, sorts, s1, s2, fsp, t)?;
     self.update_provs(sp, sorts)?;
-    self.decl_nota.entry(t).or_default().0 = true;
-    Ok(())
+    let (prefixes, infixes) = (&self.prefixes, &self.infixes);
+    let (coe, nots) = self.decl_nota.entry(t).or_default();
+    *coe = true;
+    if nots.is_empty() { return Ok(None) }
+    let related = nots.iter().filter_map(|&(ref tk, infx)| {
+      let m = if infx {infixes} else {prefixes};
+      let kind = if infx {"infix"} else {"prefix"};
+      m.get(tk).map(|n| (n.span.clone(), format!("{} notation '{}' declared here", kind, tk).into()))
+    }).collect();
+    Ok(Some(ElabError::warn_with_info(sp,
+      "coercion term also has explicit notation, which may parse ambiguously".into(), related)))
   }
 
   /// Merge environment `other` into this environment.
@@ -985,16 +1136,20 @@ impl ParserEnv {
     self.delims_l.merge(&other.delims_l);
     self.delims_r.merge(&other.delims_r);
     for (tk, &(ref fsp, p)) in &other.consts {
+      let p1 = self.consts.get(tk).map(|c| c.1);
       self.add_const(tk.clone(), fsp.clone(), p).unwrap_or_else(|r|
         errors.push(ElabError::with_info(sp,
           format!("constant '{}' declared with two precedences", tk).into(),
-          vec![(r.decl1, "declared here".into()), (r.decl2, "declared here".into())])))
+          vec![(r.decl1, format!("declared with precedence {} here", p1.unwrap()).into()),
+               (r.decl2, format!("declared with precedence {} here", p).into())])))
     }
     for (&p, &(ref fsp, r)) in &other.prec_assoc {
+      let (a1, a2) = if r {("left", "right")} else {("right", "left")};
       self.add_prec_assoc(p, fsp.clone(), r).unwrap_or_else(|r|
         errors.push(ElabError::with_info(sp,
           format!("precedence level {} has incompatible associativity", p).into(),
-          vec![(r.decl1, "left assoc here".into()), (r.decl2, "right assoc here".into())])))
+          vec![(r.decl1, format!("{} assoc here", a1).into()),
+               (r.decl2, format!("{} assoc here", a2).into())])))
     }
     for (tk, i) in &other.prefixes {
       self.add_prefix(tk.clone(), i.remap(r)).unwrap_or_else(|r|
@@ -1157,11 +1312,42 @@ impl Environment {
     }
   }
 
-  /// Add a coercion declaration to the environment.
-  pub fn add_coe(&mut self, s1: SortID, s2: SortID, fsp: FileSpan, t: TermID) -> Result<(), ElabError> {
+  /// Add a coercion declaration to the environment. Returns a warning if the coercion
+  /// shadows existing notation for `t`; see [`ParserEnv::add_coe`].
+  pub fn add_coe(&mut self, s1: SortID, s2: SortID, fsp: FileSpan, t: TermID) -> Result<Option<ElabError>, ElabError> {
     self.pe.add_coe(fsp.span, &self.sorts, s1, s2, fsp, t)
   }
 
+  /// Record the current extent of the environment, so that declarations added later
+  /// can be removed by [`rollback`].
+  ///
+  /// [`rollback`]: struct.Environment.html#method.rollback
+  pub fn snapshot(&self) -> EnvSnapshot {
+    EnvSnapshot {
+      sorts: self.sorts.len(),
+      terms: self.terms.len(),
+      thms: self.thms.len(),
+      stmts: self.stmts.len(),
+      pe: self.pe.clone(),
+    }
+  }
+
+  /// Restore the environment to the state recorded by [`snapshot`]. This removes all
+  /// sorts, terms and theorems added since, clears the corresponding `sort` and `decl`
+  /// fields of the atom data, and restores the parser environment (notations and
+  /// coercions). Atoms and lisp global definitions are not affected, and the
+  /// statement traces of lisp global definitions made since the snapshot are kept.
+  ///
+  /// [`snapshot`]: struct.Environment.html#method.snapshot
+  pub fn rollback(&mut self, snap: EnvSnapshot) {
+    for s in self.sorts.drain(snap.sorts..) { self.data[s.atom].sort = None }
+    for t in self.terms.drain(snap.terms..) { self.data[t.atom].decl = None }
+    for t in self.thms.drain(snap.thms..) { self.data[t.atom].decl = None }
+    let stmts = self.stmts.split_off(snap.stmts);
+    self.stmts.extend(stmts.into_iter().filter(|s| matches!(s, StmtTrace::Global(_))));
+    self.pe = snap.pe;
+  }
+
   /// Convert a string to an `AtomID`. This mutates the environment because we maintain
   /// the list of all allocated atoms, and two calls with the same `&str` input
   /// will yield the same `AtomID`.
@@ -1269,3 +1455,27 @@ impl Environment {
       vec![(td.span.clone(), "declared here".into())]))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fsp(n: usize) -> FileSpan {
+    FileSpan {file: std::env::current_dir().unwrap().join("test.mm1").into(), span: (n..n+1).into()}
+  }
+
+  #[test]
+  fn incompatible_decl_order() {
+    let mut pe = ParserEnv::default();
+    pe.add_const("!".into(), fsp(1), Prec::Prec(10)).unwrap();
+    let e = pe.add_const("!".into(), fsp(2), Prec::Prec(20)).unwrap_err();
+    assert_eq!((e.decl1.span.start, e.decl2.span.start), (1, 2));
+    assert!(pe.consts["!"].1 == Prec::Prec(10));
+    for &r in &[false, true] {
+      let mut pe = ParserEnv::default();
+      pe.add_prec_assoc(30, fsp(1), r).unwrap();
+      let e = pe.add_prec_assoc(30, fsp(2), !r).unwrap_err();
+      assert_eq!((e.decl1.span.start, e.decl2.span.start), (1, 2));
+    }
+  }
+}
@@ -36,12 +36,12 @@ use std::ops::Deref;
 use std::sync::Arc;
 use std::rc::Rc;
 use std::collections::{HashMap, hash_map::Entry};
-use num::BigInt;
+use num::{BigInt, BigRational};
 use super::{Spans, ObjectKind, Remap,
   environment::{Environment, ParserEnv,
     AtomVec, TermVec, ThmVec, SortVec, DeclKey, StmtTrace,
     SortID, TermID, ThmID, AtomID, Sort, Term, Thm, AtomData},
-  lisp::{LispVal, LispKind, LispRef, LispRemapper,
+  lisp::{LispVal, LispKind, LispRef, LispWeak, LispRemapper,
     InferTarget, Proc, Annot, Syntax, print::FormatEnv}};
 use crate::util::{ArcString, FileSpan, Span};
 use crate::{lined_string::LinedString, __mk_lisp_kind};
@@ -57,6 +57,16 @@ impl FrozenEnv {
   /// Create a new `FrozenEnv` from an Environment.
   pub fn new(env: Environment) -> Self { Self(Arc::new(env)) }
 
+  /// Temporarily freeze an environment under construction, to run a read only
+  /// operation `f` on it. The environment is restored afterwards.
+  /// The `FrozenEnv` should not escape `f`, or else this will panic.
+  pub fn with_frozen<R>(env: &mut Environment, f: impl FnOnce(&FrozenEnv) -> R) -> R {
+    let fe = Self::new(std::mem::replace(env, Environment::new()));
+    let r = f(&fe);
+    *env = Arc::try_unwrap(fe.0).unwrap_or_else(|_| panic!("frozen environment escaped"));
+    r
+  }
+
   /// Convert a `&FrozenEnv` into an `&Environment`.
   /// # Safety
   /// The reference derived here is only usable for reading, so in particular
@@ -131,13 +141,17 @@ pub struct FrozenLispVal(LispVal);
 #[derive(Debug, DeepSizeOf)]
 pub struct FrozenLispRef(LispRef);
 
+/// A wrapper around a [`LispWeak`](../lisp/struct.LispWeak.html) that is frozen.
+#[derive(Debug, DeepSizeOf)]
+pub struct FrozenLispWeak(LispWeak);
+
 /// A wrapper around a [`Proc`](../lisp/struct.Proc.html) that is frozen.
 #[derive(Debug, DeepSizeOf)]
 pub struct FrozenProc(Proc);
 
 __mk_lisp_kind! {
   /// A wrapper around a [`LispKind`](../lisp/struct.LispKind.html) that is frozen.
-  FrozenLispKind, FrozenLispVal, FrozenLispRef, FrozenProc
+  FrozenLispKind, FrozenLispVal, FrozenLispRef, FrozenLispWeak, FrozenProc
 }
 
 impl LispKind {
@@ -312,9 +326,13 @@ impl Remap<LispRemapper> for FrozenLispVal {
           ref_
         }
       },
+      // Upgrading the weak reference here would race with other readers,
+      // so weak references do not survive the import.
+      FrozenLispKind::WeakRef(_) => LispVal::new(LispKind::WeakRef(LispWeak::default())),
       &FrozenLispKind::MVar(n, is) => LispVal::new(LispKind::MVar(n, is.remap(r))),
       FrozenLispKind::Goal(e) => LispVal::new(LispKind::Goal(e.remap(r))),
       FrozenLispKind::Number(n) => LispVal::number(n.clone()),
+      FrozenLispKind::Ratio(q) => LispVal::new(LispKind::Ratio(q.clone())),
       FrozenLispKind::String(s) => LispVal::string(s.clone()),
       &FrozenLispKind::Bool(b) => LispVal::bool(b),
       &FrozenLispKind::Syntax(s) => LispVal::syntax(s),
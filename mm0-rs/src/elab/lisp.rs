@@ -11,12 +11,12 @@ pub mod pretty;
 
 use std::ops::{Deref, DerefMut};
 use std::hash::Hash;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::cell::{Cell, RefCell};
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use std::str::FromStr;
-use num::BigInt;
+use num::{BigInt, BigRational};
 use owning_ref::{OwningRef, StableAddress, CloneStableAddress};
 use crate::parser::ast::Atom;
 use crate::util::{ArcString, FileSpan, Span, SliceExt};
@@ -156,7 +156,7 @@ pub struct LispVal(Rc<LispKind>);
 /// [`FrozenLispKind`]: ../frozen/enum.FrozenLispKind.html
 #[macro_export]
 macro_rules! __mk_lisp_kind {
-  ($(#[$doc:meta])* $kind:ident, $val:ident, $ref_:ident, $proc:ident) => {
+  ($(#[$doc:meta])* $kind:ident, $val:ident, $ref_:ident, $weak:ident, $proc:ident) => {
     $(#[$doc])*
     #[derive(Debug, DeepSizeOf)]
     pub enum $kind {
@@ -181,6 +181,10 @@ macro_rules! __mk_lisp_kind {
       /// A number like `123`. These use bignum arithmetic so that client code
       /// doesn't have to worry about overflow.
       Number(BigInt),
+      /// A rational number like `1/2`. Values of this kind are always kept in
+      /// lowest terms with a denominator greater than 1; integer results are
+      /// stored as `Number` instead.
+      Ratio(BigRational),
       /// An immutable string like `"foo"`.
       String(ArcString),
       /// A boolean value, `#t` or `#f`.
@@ -196,6 +200,10 @@ macro_rules! __mk_lisp_kind {
       /// A mutable reference. This is the only way to have mutable values in
       /// client code.
       Ref($ref_),
+      /// A weak reference to a `Ref`, created by `weak-ref`. This does not keep the
+      /// target alive, so it can be used to build cyclic data structures that can
+      /// still be freed.
+      WeakRef($weak),
       /// A metavariable. The `usize` gives the index of the metavariable in the
       /// local context, and the `InferTarget` is the expected type of the expression
       /// that should replace this metavariable.
@@ -211,6 +219,7 @@ __mk_lisp_kind! {
   LispKind,
   LispVal,
   LispRef,
+  LispWeak,
   Proc
 }
 
@@ -218,6 +227,13 @@ __mk_lisp_kind! {
 #[derive(Debug, DeepSizeOf)]
 pub struct LispRef(RefCell<LispVal>);
 
+/// A weak reference to a `Ref` value, the inner type used by `weak-ref` and `upgrade`.
+/// Since the target is not owned by the weak reference, it is not counted
+/// in the `DeepSizeOf` of the weak reference (it is counted by its strong owners).
+#[derive(Debug, Default)]
+pub struct LispWeak(Weak<LispKind>);
+crate::deep_size_0!({!Copy} LispWeak);
+
 impl LispVal {
   /// Make a `LispVal` from the inner enum type `LispKind`.
   pub fn new(e: LispKind) -> LispVal { LispVal(Rc::new(e)) }
@@ -231,6 +247,12 @@ impl LispVal {
   }
   /// Construct a `LispVal` for an improper list.
   pub fn number(n: BigInt) -> LispVal { LispVal::new(LispKind::Number(n)) }
+  /// Construct a `LispVal` for a rational number, which is normalized to a
+  /// `Number` if the denominator is 1.
+  pub fn ratio(q: BigRational) -> LispVal {
+    if q.is_integer() { LispVal::number(q.to_integer()) }
+    else { LispVal::new(LispKind::Ratio(q)) }
+  }
   /// Construct a `LispVal` for a string.
   pub fn string(s: ArcString) -> LispVal { LispVal::new(LispKind::String(s)) }
   /// Construct a `LispVal` for a syntax element.
@@ -264,6 +286,22 @@ impl LispVal {
     }
   }
 
+  /// Make a copy of this object with the given span on every node of its
+  /// list structure, replacing any existing spans. References and maps are
+  /// returned unchanged, to preserve their identity.
+  pub fn replace_span_deep(&self, fsp: &FileSpan) -> LispVal {
+    match &**self {
+      LispKind::Annot(_, v) => v.replace_span_deep(fsp),
+      LispKind::Ref(_) | LispKind::WeakRef(_) | LispKind::AtomMap(_) => self.clone(),
+      LispKind::List(es) => LispVal::list(
+        es.iter().map(|e| e.replace_span_deep(fsp)).collect::<Vec<_>>()).span(fsp.clone()),
+      LispKind::DottedList(es, r) => LispVal::dotted_list(
+        es.iter().map(|e| e.replace_span_deep(fsp)).collect::<Vec<_>>(),
+        r.replace_span_deep(fsp)).span(fsp.clone()),
+      _ => self.clone().span(fsp.clone())
+    }
+  }
+
   /// Get a mutable reference to the inner `LispKind`, if possible, returning
   /// `None` if the value is shared and calling `f` with the inner reference if
   /// there is only one owner.
@@ -284,6 +322,15 @@ impl LispVal {
     }
   }
 
+  /// Construct a weak reference to this value, if it is a mutable reference.
+  pub fn downgrade(&self) -> Option<LispVal> {
+    match &*self.0 {
+      LispKind::Ref(_) => Some(LispVal::new(LispKind::WeakRef(LispWeak(Rc::downgrade(&self.0))))),
+      LispKind::Annot(_, v) => v.downgrade(),
+      _ => None,
+    }
+  }
+
   /// Returns true if this is a clone of `e`.
   pub fn ptr_eq(&self, e: &Self) -> bool { Rc::ptr_eq(&self.0, &e.0) }
   /// Try to get at the inner data, if this value is not shared,
@@ -373,6 +420,11 @@ impl LispRef {
   }
 }
 
+impl LispWeak {
+  /// Get the target of the weak reference, or `None` if it has been deallocated.
+  pub fn upgrade(&self) -> Option<LispVal> { self.0.upgrade().map(LispVal) }
+}
+
 impl PartialEq<LispRef> for LispRef {
   fn eq(&self, other: &LispRef) -> bool { *self.get() == *other.get() }
 }
@@ -434,6 +486,10 @@ impl LispKind {
   pub fn is_int(&self) -> bool {
     self.unwrapped(|e| matches!(e, LispKind::Number(_)))
   }
+  /// Returns true if this value is a (non-integer) rational number.
+  pub fn is_ratio(&self) -> bool {
+    self.unwrapped(|e| matches!(e, LispKind::Ratio(_)))
+  }
   /// Get the number that this value stores, if applicable.
   pub fn as_int<T>(&self, f: impl FnOnce(&BigInt) -> T) -> Option<T> {
     self.unwrapped(|e| if let LispKind::Number(n) = e {Some(f(n))} else {None})
@@ -471,6 +527,15 @@ impl LispKind {
       _ => false,
     }
   }
+  /// Get the underlying reference cell, if this value is a mutable reference.
+  /// (This function, unlike most, does not auto-deref references.)
+  pub fn as_lref(&self) -> Option<&LispRef> {
+    match self {
+      LispKind::Ref(m) => Some(m),
+      LispKind::Annot(_, v) => v.as_lref(),
+      _ => None,
+    }
+  }
   /// Get a mutable reference to the value stored by the reference, if it is one.
   pub fn as_ref_<T>(&self, f: impl FnOnce(&mut LispVal) -> T) -> Option<T> {
     match self {
@@ -587,6 +652,7 @@ impl PartialEq<LispKind> for LispKind {
     self.unwrapped(|s| other.unwrapped(|o| match (s, o) {
       (&LispKind::Atom(a), &LispKind::Atom(b)) => a == b,
       (LispKind::Number(a), LispKind::Number(b)) => a == b,
+      (LispKind::Ratio(a), LispKind::Ratio(b)) => a == b,
       (LispKind::String(a), LispKind::String(b)) => a == b,
       (LispKind::Bool(a), LispKind::Bool(b)) => a == b,
       (LispKind::Syntax(a), LispKind::Syntax(b)) => a == b,
@@ -759,7 +825,18 @@ str_enum! {
     /// derived from the value `sp` (one can use `copy-span` to pass a value with the
     /// right span here), with error type `type`, which can be `'error`, `'info` or
     /// `'warn`. If `sp` is `#t`, then it will also display a stack trace.
+    /// `(report-at sp type msg related)` additionally attaches related information to
+    /// the message, where `related` is a list of `(sp msg)` pairs giving other
+    /// locations (which may be in other files) relevant to the message.
     ReportAt: "report-at",
+    /// `(annotate-span sp msg)` sets the hover text for the span of the value `sp` to
+    /// the string `msg`, so that it is displayed when hovering over that span in the editor.
+    /// Spans outside the current file or statement are ignored.
+    AnnotateSpan: "annotate-span",
+    /// `(annotate-goto sp target)` makes go-to-definition on the span of the value `sp`
+    /// jump to the span of the value `target`, which may be in another file.
+    /// Spans `sp` outside the current file or statement are ignored.
+    AnnotateGoto: "annotate-goto",
     /// `begin` returns its last argument, or `#undef` if it is given no arguments.
     /// In Scheme this is a syntax form, but in MM1 all functions have the same
     /// evaluation semantics as `begin`, so the only interesting thing this function
@@ -771,21 +848,45 @@ str_enum! {
     /// head of the list. `(apply)` is an error, and if `f` is a syntax form then this
     /// is also an error, i.e. `(apply def (x 5))` does not work.
     Apply: "apply",
-    /// `(+ a b c)` computes the sum of the (integer) arguments. `(+)` is zero and `(+ a)` is `a`.
+    /// `(+ a b c)` computes the sum of the (integer or rational) arguments.
+    /// `(+)` is zero and `(+ a)` is `a`.
     Add: "+",
-    /// `(* a b c)` computes the product of the (integer) arguments. `(*)` is one and `(* a)` is `a`.
+    /// `(* a b c)` computes the product of the (integer or rational) arguments.
+    /// `(*)` is one and `(* a)` is `a`.
     Mul: "*",
-    /// `(max a b c)` computes the maximum of the (integer) arguments. `(max)` is an error.
+    /// `(max a b c)` computes the maximum of the (integer or rational) arguments.
+    /// `(max)` is an error.
     Max: "max",
-    /// `(min a b c)` computes the minimum of the (integer) arguments. `(min)` is an error.
+    /// `(min a b c)` computes the minimum of the (integer or rational) arguments.
+    /// `(min)` is an error.
     Min: "min",
+    /// `(abs n)` computes the absolute value of the integer or rational number `n`.
+    Abs: "abs",
+    /// `(sign n)` returns `-1`, `0` or `1` according to whether the number `n`
+    /// is negative, zero or positive.
+    Sign: "sign",
     /// `(- a b)` computes the subtraction `a - b`. `(- a b c)` is `a - b - c`,
     /// `(- a)` is `-a`, and `(-)` is an error.
     Sub: "-",
     /// {a // b}` computes the integer (flooring) division. More arguments associate to the left.
+    /// If any argument is a rational number, this is exact rational division instead.
     Div: "//",
     /// `{a % b}` computes the integer modulus. More arguments associate to the left.
+    /// If any argument is a rational number, this is the rational remainder
+    /// `a - b * n` instead, where `n` is `a / b` rounded toward zero.
     Mod: "%",
+    /// `(ratio n d)` constructs the rational number `n / d` in lowest terms.
+    /// If `d` divides `n` the result is an integer.
+    Ratio: "ratio",
+    /// `(set-seed n)` sets the seed of the pseudo-random number generator used by
+    /// `random`, so that randomized tactics can be made reproducible.
+    SetSeed: "set-seed",
+    /// `(random n)` returns a pseudo-random integer `k` with `0 <= k < n`.
+    /// The sequence of results is determined by the last call to `set-seed`.
+    Random: "random",
+    /// `(choose xs)` returns a pseudo-random element of the nonempty list `xs`,
+    /// using the same generator as `random`.
+    Choose: "choose",
     /// `{a < b}` is true if `a` is less than `b`. `(< a b c)` means `a < b` and `b < c`.
     Lt: "<",
     /// `{a <= b}` is true if `a` is less or equal to `b`. `(<= a b c)` means `a <= b` and `b <= c`.
@@ -834,6 +935,42 @@ str_enum! {
     /// (string-append "foo" 'bar 42) -- "foobar42"
     /// ```
     StringAppend: "string-append",
+    /// `(format s e1 e2 e3)` returns the string `s` with each occurrence of `{}` replaced
+    /// by the next argument, stringified as in `->string`. `{{` and `}}` are literal braces.
+    /// It is an error if the number of arguments does not match the number of `{}`.
+    /// ```text
+    /// (format "{} + {} = {}" 'x 1 "y")  -- "x + 1 = y"
+    /// ```
+    Format: "format",
+    /// `(string-contains? s t)` returns true if the string `t` appears in the string `s`.
+    /// If `t` is empty this is always true.
+    StringContains: "string-contains?",
+    /// `(string-starts-with? s t)` returns true if the string `s` begins with the string `t`.
+    /// If `t` is empty this is always true.
+    StringStartsWith: "string-starts-with?",
+    /// `(string-ends-with? s t)` returns true if the string `s` ends with the string `t`.
+    /// If `t` is empty this is always true.
+    StringEndsWith: "string-ends-with?",
+    /// `(string-index-of t s)` returns the index of the first occurrence of the string `t`
+    /// in the string `s`, counted in characters (not bytes), or `#undef` if `t` does not
+    /// appear in `s`. (Use `def?` to test the result.)
+    /// ```text
+    /// (string-index-of "b" "abc")  -- 1
+    /// (string-index-of "d" "abc")  -- #undef
+    /// ```
+    StringIndexOf: "string-index-of",
+    /// `(string-upcase s)` converts the string `s` to upper case. This uses the Unicode
+    /// case mapping, so the result may be longer than `s`: `(string-upcase "ß")` is `"SS"`.
+    StringUpcase: "string-upcase",
+    /// `(string-downcase s)` converts the string `s` to lower case, using the Unicode
+    /// case mapping.
+    StringDowncase: "string-downcase",
+    /// `(char->number s)` returns the Unicode scalar value of the single character
+    /// in the string `s`. It is an error if `s` does not have exactly one character.
+    CharToNumber: "char->number",
+    /// `(number->char n)` returns a string containing the single character with
+    /// Unicode scalar value `n`. It is an error if `n` is not a valid scalar value.
+    NumberToChar: "number->char",
     /// `(not e1 e2 e3)` returns `#f` if any argument is truthy, and `#t` otherwise.
     /// It is not short-circuiting.
     Not: "not",
@@ -864,6 +1001,19 @@ str_enum! {
     /// calling `f` on the heads of all the arguments, then the second elements and so on.
     /// All lists must be the same length.
     Map: "map",
+    /// * `(range n)` returns the list `(0 1 ... n-1)`.
+    /// * `(range a b)` returns the list `(a a+1 ... b-1)`.
+    /// * `(range a b step)` counts from `a` towards `b` (exclusive) in increments
+    ///   of `step`, which may be negative to count down. A zero step is an error.
+    ///
+    /// The list is constructed eagerly, so a very large range will consume a lot of memory;
+    /// construction is interrupted by the timeout like any other long running computation.
+    /// ```text
+    /// (range 3)        -- (0 1 2)
+    /// (range 2 5)      -- (2 3 4)
+    /// (range 5 0 -2)   -- (5 3 1)
+    /// ```
+    Range: "range",
     /// `(bool? e)` is true if the argument is a boolean, `#t` or `#f`.
     IsBool: "bool?",
     /// `(atom? e)` is true if the argument is an atom (also known as a symbol), `'x`.
@@ -873,8 +1023,10 @@ str_enum! {
     IsPair: "pair?",
     /// `(null? e)` is true if its argument is `()`.
     IsNull: "null?",
-    /// `(number? e)` is true if the argument is an integer.
+    /// `(number? e)` is true if the argument is an integer or a rational number.
     IsNumber: "number?",
+    /// `(ratio? e)` is true if the argument is a rational number which is not an integer.
+    IsRatio: "ratio?",
     /// `(string? e)` is true if its argument is a string (not a formula or atom).
     IsString: "string?",
     /// `(fn? e)` is true if the argument is a procedure.
@@ -883,6 +1035,18 @@ str_enum! {
     IsDef: "def?",
     /// `(ref? e)` is true if the argument is a ref-cell.
     IsRef: "ref?",
+    /// `(weak-ref r)` returns a weak reference to the ref-cell `r`. A weak reference
+    /// does not keep `r` alive, so it can be used to break reference cycles in
+    /// data structures built with `ref!`. Use `upgrade` to get `r` back.
+    WeakRef: "weak-ref",
+    /// `(upgrade w)` returns the ref-cell that the weak reference `w` points to,
+    /// or `#undef` if there are no more strong references to it.
+    Upgrade: "upgrade",
+    /// `(ref-eq? r1 r2)` is true if `r1` and `r2` are the same ref-cell, that is,
+    /// if a `set!` on one would be visible through the other. This is unlike
+    /// `equal?`, which compares the values stored in the cells.
+    /// It is an error to pass an argument that is not a ref-cell.
+    RefEq: "ref-eq?",
     /// * `(ref! e)` constructs a new ref-cell containing the value `e`.
     /// * `(ref!)` constructs a new ref-cell containing `#undef`.
     NewRef: "ref!",
@@ -894,11 +1058,36 @@ str_enum! {
     /// (This can be used for improved error reporting, but
     /// otherwise has no effect on program semantics.)
     CopySpan: "copy-span",
+    /// `(copy-span-deep from to)` is like `(copy-span from to)`, but it also copies
+    /// the position information to every subterm of `to`, so that errors reported on
+    /// subterms point to `from` as well. References and maps inside `to` are not copied.
+    CopySpanDeep: "copy-span-deep",
     ///  `(stack-span n)` gets the span from `n` calls up the stack (where `0` is
     /// the currently executing function). Returns `#undef` tagged with the target span,
     /// which can then be copied to a term using `(copy-span)`.
     /// (Useful for targeted error reporting in scripts.)
     StackSpan: "stack-span",
+    /// `(span-stack)` returns the list of all spans that `(stack-span n)` would return,
+    /// innermost first, so that `(nth n (span-stack))` is the same as `(stack-span n)`.
+    SpanStack: "span-stack",
+    /// `(span-text sp)` returns the text of the current file covered by the span of `sp`,
+    /// as a string. Returns `#undef` if `sp` has no span or the span is in another file.
+    SpanText: "span-text",
+    /// `(span-start sp)` returns the byte offset of the start of the span of `sp`,
+    /// or `#undef` if `sp` has no span.
+    SpanStart: "span-start",
+    /// `(span-end sp)` returns the byte offset of the end of the span of `sp`,
+    /// or `#undef` if `sp` has no span.
+    SpanEnd: "span-end",
+    /// `(span-file sp)` returns the path of the file containing the span of `sp`,
+    /// relative to the project root, or `#undef` if `sp` has no span.
+    SpanFile: "span-file",
+    /// `(make-span file start end)` returns `#undef` tagged with the span from byte offset
+    /// `start` to `end` in `file`, which can then be used with `copy-span` or `report-at`.
+    /// `file` is a path relative to the project root (as returned by `span-file`), and
+    /// must be the current file or a file imported by it. For the current file, the span
+    /// must lie within the file.
+    MakeSpan: "make-span",
     /// `(async f args)` evaluates `(f args)` on another thread, and returns a
     /// procedure that will join on the thread to wait for the result.
     Async: "async",
@@ -913,15 +1102,33 @@ str_enum! {
     /// * `(insert! m k v)` inserts the value `v` at key `k` in the mutable map `m`,
     ///   and returns `#undef`.
     /// * `(insert! m k)` "undefines" the value at key `k` in `m`, that is,
-    ///   it erases whatever is there.
+    ///   it erases whatever is there. (`map-remove!` is preferred for this.)
     Insert: "insert!",
+    /// `(map-remove! m k)` removes the key `k` from the mutable map `m`,
+    /// and returns the value that was stored there, or `#undef` if it was not present.
+    MapRemove: "map-remove!",
     /// * `(insert m k v)` returns an immutable map based on the immutable map `m`,
     ///   with the value `v` inserted at key `k`.
     /// * `(insert m k)` returns `k` erased from `m`.
     InsertNew: "insert",
+    /// `(merge-maps m1 m2 ...)` returns a new mutable atom map containing the entries
+    /// of all the given maps. If a key appears in more than one map, the value from
+    /// the later map wins. The arguments are not modified, and `(merge-maps)` is an
+    /// empty map, so this behaves like `atom-map!` with the entries pre-filled.
+    MergeMaps: "merge-maps",
+    /// `(map-copy m)` returns a new mutable atom map with the same entries as `m`.
+    /// Later changes to either map are not visible in the other, so this can be used
+    /// to save a copy of some state before making speculative changes to it.
+    MapCopy: "map-copy",
     /// `(set-timeout n)` sets the timeout for running individual theorems and
     /// `do` blocks to `n` milliseconds. The default is 5 seconds.
     SetTimeout: "set-timeout",
+    /// `(with-step-budget n f)` calls the thunk `f` with no arguments, and fails
+    /// with an error if evaluating it takes more than `n` evaluation steps. Unlike
+    /// `set-timeout`, this is deterministic, so it can be used to bound the cost
+    /// of automation. Nested budgets can only decrease the outer budget, and the
+    /// steps used by `f` are counted against any enclosing budget.
+    WithStepBudget: "with-step-budget",
     /// `(mvar? e)` returns `#t` if `e` is an unsolved metavariable value.
     /// *Note:* Holes in expressions are *not* represented as raw metavariables,
     /// they are ref-cells to metavariables. So to test if a metavariable has not
@@ -938,11 +1145,19 @@ str_enum! {
     /// (mvar? (get! (mvar! "foo" #t)))     -- #t
     /// ```
     NewMVar: "mvar!",
+    /// `(assign-mvar mv e)` assigns the metavariable `mv` to the expression `e`,
+    /// and returns `#undef`. It is an error if `e` does not have the sort that `mv`
+    /// expects, or if `mv` occurs in `e`.
+    AssignMVar: "assign-mvar",
     /// `(pp e)` pretty-prints a (fully elaborated) term expression using declared
     /// math notations. It relies on the theorem context to typecheck the formulas
     /// and provide context, and will fall back on the generic lisp printer
     /// for things it doesn't understand.
     PrettyPrint: "pp",
+    /// `(pp-width e n)` is the same as `(pp e)`, but with a line width of `n` characters
+    /// instead of 80. The width must be positive, and widths larger than 10000 are
+    /// treated as 10000.
+    PrettyPrintWidth: "pp-width",
     /// `(goal e)` creates a new goal value given a statement expression.
     /// It will need to be wrapped with a `ref!` to be used with `set-goals`.
     NewGoal: "goal",
@@ -951,6 +1166,10 @@ str_enum! {
     /// `(infer-type p)` gets the statement proven by the proof `p`.
     /// This does not perform full typechecking on `p`.
     InferType: "infer-type",
+    /// `(infer-sort e)` gets the sort of the expression `e`, which should be a variable
+    /// in the local context or a term application. If `e` is a metavariable, it returns
+    /// the sort of the metavariable, or `#undef` if the sort is not known.
+    InferSort: "infer-sort",
     /// `(get-mvars)` returns the current list of active metavariables.
     GetMVars: "get-mvars",
     /// `(get-goals)` returns the current goal list, a list of references to goals.
@@ -960,6 +1179,42 @@ str_enum! {
     /// the current goal list. If any of the provided goals are already assigned
     /// they are removed from the list.
     SetGoals: "set-goals",
+    /// `(goals-closed-by f)` calls the thunk `f` with no arguments, and returns the
+    /// list of goals that were in the goal list before the call and have been solved
+    /// or removed from the goal list afterward.
+    GoalsClosedBy: "goals-closed-by",
+    /// `(made-progress? f)` calls the thunk `f` with no arguments, and returns `#t` if
+    /// it changed the proof state, meaning that the goal list or the type of a goal
+    /// was changed, or a hypothesis was added or removed. This can be used to detect
+    /// tactics that do nothing, for example to stop a loop.
+    MadeProgress: "made-progress?",
+    /// `(now f)` focuses on the first unsolved goal and calls the thunk `f` with no
+    /// arguments, and fails with an error unless `f` solves the goal completely, leaving
+    /// no unsolved subgoals. The other goals are restored afterward.
+    Now: "now",
+    /// `(focus-on pred f1 f2 ...)` calls `(pred e)` on the type `e` of each goal in turn,
+    /// and focuses on the first goal for which `pred` returns true, as if it were moved
+    /// to the front of the goal list and `(focus (f1) (f2) ...)` were called. That is,
+    /// the thunks `f1`, `f2`, ... are called with no arguments in order, and
+    /// the goal must be solved at the end, after which the other goals are restored.
+    /// It is an error if no goal satisfies `pred`.
+    FocusOn: "focus-on",
+    /// `(solve f)` calls the thunk `f` with no arguments, and returns `#t` if
+    /// all goals are solved afterward. Otherwise, the proof state is rolled back to
+    /// what it was before the call, including any metavariable assignments,
+    /// and `(solve f)` returns `#f`.
+    Solve: "solve",
+    /// `(try f)` calls the thunk `f` with no arguments and returns the result. If `f`
+    /// fails with an error, the error is discarded, the proof state is rolled back to
+    /// what it was before the call, and `(try f)` returns `#undef`.
+    /// (Timeouts and cancellation are not caught.)
+    Try: "try",
+    /// * `(rotate-goals n)` rotates the goal list left by `n` places, so that the goal at
+    ///   index `n` becomes the first goal. A negative `n` rotates to the right.
+    /// * `(rotate-goals)` is the same as `(rotate-goals 1)`, which moves the first goal to the end.
+    RotateGoals: "rotate-goals",
+    /// `(swap-goals i j)` swaps the goals at (zero-based) indices `i` and `j` in the goal list.
+    SwapGoals: "swap-goals",
     /// `(set-close-fn f)` sets the "closer" for the current proof to `f`.
     /// It will be called with no arguments at the end of a `focus` block, and is
     /// responsible for reporting all unfinished goals. Passing `#undef` instead of
@@ -968,6 +1223,22 @@ str_enum! {
     /// `(local-ctx)` returns the list of hypothesis names (`(infer-type)`
     /// can be used to get the type of the hypotheses).
     LocalCtx: "local-ctx",
+    /// `(get-hyp h)` returns `(h e p)` where `e` is the stored type and `p` is the proof
+    /// of the hypothesis or subproof named `h` in the local context, or `#undef` if
+    /// there is no such hypothesis.
+    GetHyp: "get-hyp",
+    /// * `(goal-context)` returns the list of hypotheses and subproofs in the local context,
+    ///   in order, as a list of `(h e)` pairs where `e` is the type of `h`. Shadowed
+    ///   subproofs are not included.
+    /// * `(goal-context t)` is the same, but returns only the hypotheses whose type
+    ///   is an application of the term `t`.
+    GoalContext: "goal-context",
+    /// `(clear-hyp h)` removes the hypothesis or subproof named `h` from the local
+    /// context, so that it no longer appears in `(stat)` or `(local-ctx)`. If `h`
+    /// shadowed an earlier subproof with the same name, that one becomes visible again.
+    /// Proofs refer to subproofs by name, so `h` should not be cleared if it has
+    /// already been used in the proof.
+    ClearHyp: "clear-hyp",
     ///`(to-expr e)` elaborates a term pre-expression into an expression,
     /// producing metavariables for `_` placeholders in the expression.
     ToExpr: "to-expr",
@@ -975,15 +1246,101 @@ str_enum! {
     ///   its type against the first goal.
     /// * `(refine p1 p2 p3)` applies three proof pre-expressions to the first
     ///   three goals. If there are fewer than three goals the remaining proofs are ignored.
+    /// * `(refine :tgt e p)` elaborates the expression `e` and then the proof pre-expression `p`
+    ///   with `e` as the expected type, and returns the resulting proof, without
+    ///   using or changing the current goals (although `p` may create new goals).
     Refine: "refine",
     /// * `(have h p)` elaborates the proof pre-expression `p` to a proof, infers
     ///   the type `e` of the proof, and adds `e` to the list of proven subproofs,
     ///   after which `h` may be referred to like any other theorem hypothesis.
     /// * `(have h e p)` is the same except that `p` is elaborated with `e` as the expected type.
     Have: "have",
+    /// `(symm)` is the symmetry tactic. It applies to a goal of the form `|- (eq a b)`,
+    /// where `eq` is a binary term constructor, and replaces it with the goal `|- (eq b a)`.
+    /// For this to work there must be a theorem of the form `(eq x y) > (eq y x)` in the
+    /// environment, which is used to close the original goal.
+    Symm: "symm",
+    /// `(trans b)` is the transitivity tactic. It applies to a goal of the form
+    /// `|- (eq a c)`, where `eq` is a binary term constructor, and replaces it with the
+    /// two goals `|- (eq a b)` and `|- (eq b c)`, where `b` is a term pre-expression.
+    /// For this to work there must be a theorem of the form `(eq x y) > (eq y z) > (eq x z)`
+    /// in the environment, which is used to close the original goal.
+    Trans: "trans",
+    /// `(expr-diff e1 e2)` compares two expressions, returning `#undef` if they are equal,
+    /// and otherwise returns `(path a1 a2)` where `a1` and `a2` are the first differing
+    /// subterms of `e1` and `e2`, and `path` is the list of argument positions leading
+    /// to them (where `1` is the first argument of an application).
+    /// ```text
+    /// (expr-diff '(foo (bar x) y) '(foo (bar z) y))  -- ((1 1) x z)
+    /// (expr-diff '(foo x) '(foo x))                  -- #undef
+    /// ```
+    ExprDiff: "expr-diff",
+    /// `(expr-diff-pretty e1 e2)` returns a string displaying `e1` and `e2` on consecutive
+    /// lines, with the first differing subterm (as reported by `expr-diff`) marked with `«»`.
+    /// ```text
+    /// (expr-diff-pretty '(foo (bar x) y) '(foo (bar z) y))
+    /// -- "(foo (bar «x») y)\n(foo (bar «z») y)"
+    /// ```
+    ExprDiffPretty: "expr-diff-pretty",
+    /// `(start-recording)` begins recording calls to tactics like `refine` and `have`,
+    /// discarding any previous recording.
+    StartRecording: "start-recording",
+    /// `(stop-recording)` stops recording tactic calls, and returns the list of recorded
+    /// calls, in the form `(f args...)` where `f` is the name of the tactic.
+    StopRecording: "stop-recording",
+    /// * `(replay)` re-executes the most recently recorded sequence of tactic calls
+    ///   on the current proof state.
+    /// * `(replay calls)` re-executes the given list of calls, as returned by `stop-recording`.
+    Replay: "replay",
+    /// `(proof-script)` returns the most recently recorded sequence of tactic calls
+    /// (see `start-recording`) as a lisp expression `(focus (f 'a1 'a2) ...)` which will
+    /// reproduce the proof when evaluated. All arguments are quoted, so calls that
+    /// pass values without a printed representation (such as goal references) will
+    /// not replay correctly.
+    ProofScript: "proof-script",
+    /// `(provable? thm1 thm2 ...)` is a fast heuristic check for whether the first goal
+    /// can be proven using the given theorems (and the hypotheses in the local context).
+    /// It performs a shallow search, up to depth 3 and without backtracking: at each step
+    /// the first theorem whose conclusion matches the goal is used, and its hypotheses
+    /// become the new goals. A `#t` result is not a guarantee that the goal is provable,
+    /// and `#f` does not mean that it isn't.
+    Provable: "provable?",
     /// `(stat)` prints the current proof state, which consists of a list of
     /// subproofs, a list of goals, and a list of metavariables accompanied by their sorts.
     Stat: "stat",
+    /// `(export-state)` returns the current proof state as a JSON string, for use by
+    /// editor integrations. The schema is:
+    /// ```text
+    /// {"goals": [{"type": string, "span": [start, end]}, ...],
+    ///  "hyps": [{"name": string, "type": string, "span": [start, end]}, ...]}
+    /// ```
+    /// where `type` is the pretty printed type of the goal or hypothesis, and `span` is a
+    /// pair of byte offsets into the current file, pointing to the source of the goal or
+    /// hypothesis if it is known, and otherwise to the `export-state` call.
+    /// Goals are listed in the order they will be solved, and hypotheses in the order
+    /// they were introduced (the same as `stat`).
+    ExportState: "export-state",
+    /// `(get-stat)` returns the current proof state as data, in the form
+    /// `((hyps...) (goals...))`, where each hypothesis is a pair `(h e)` of the
+    /// subproof name and its type, and each goal is the type of an unassigned goal.
+    GetStat: "get-stat",
+    /// `(current-decl)` returns the name of the term, definition, axiom or theorem whose
+    /// declaration is currently being elaborated (for example, when running a tactic in
+    /// its proof), or `#undef` if there is none, such as in a `do` block.
+    CurrentDecl: "current-decl",
+    /// `(thms-by-head t)` returns the list of theorems whose conclusion is an application
+    /// of the term constructor `t`, in declaration order. This uses an index that is
+    /// updated as theorems are added, so it is faster than searching all theorems.
+    ThmsByHead: "thms-by-head",
+    /// `(unused-defs)` returns the list of definitions that are not used in the
+    /// statement or proof of any other declaration, and are not marked `local`.
+    /// These are candidates for removal or for being marked `local`.
+    UnusedDefs: "unused-defs",
+    /// `(audit-deps)` recomputes the dependencies of the value of every definition, and
+    /// returns the list of definitions whose value depends on bound variables that are
+    /// not in the declared dependencies of the return type. (This should never happen for
+    /// definitions checked by the elaborator, but `add-decl!` does not check it.)
+    AuditDeps: "audit-deps",
     /// `(get-decl x)` returns the declaration information associated to declaration `x`.
     /// The result has one of the following forms:
     ///
@@ -1010,7 +1367,177 @@ str_enum! {
     ///    and `vtask` is a thunk that will return a list `(ds proof)` where `ds` is the list
     ///    or atom map of dummy variables, and `proof` is the proof s-expression. `vtask`
     ///    can also have the form `(ds proof)` itself.
+    ///
+    /// `(get-decl x #t)` is the same, except that for theorems the proof is computed
+    /// immediately, so that `vtask` is replaced by the `(ds proof)` list (or `:sorry` if the
+    /// proof is missing or failed to check).
     GetDecl: "get-decl",
+    /// `(get-binders x)` returns the binders of the term or theorem `x`, as a list of
+    /// `(a bound? s deps)` records, where `a` is the variable name (or `_` if it is
+    /// anonymous), `bound?` is `#t` for bound variables and `#f` for regular variables,
+    /// `s` is the sort, and `deps` is the list of bound variables that the variable
+    /// depends on (always empty for bound variables).
+    GetBinders: "get-binders",
+    /// `(thm-hyps x)` returns the hypotheses of the axiom or theorem `x`, as a list of
+    /// `(h hyp)` pairs in the same format as the `hyps` component of `get-decl`.
+    ThmHyps: "thm-hyps",
+    /// `(thm-concl x)` returns the conclusion of the axiom or theorem `x`, in the same
+    /// format as the `ret` component of `get-decl`.
+    ThmConcl: "thm-concl",
+    /// `(get-sort s)` returns the declaration information associated to the sort `s`,
+    /// or `#undef` if `s` is not a sort. The result has the form `('sort s mods)`,
+    /// where `mods` is a list of the sort modifiers, a subset of
+    /// `'pure`, `'strict`, `'provable`, `'free`.
+    /// ```text
+    /// (get-sort 'wff)  -- (sort wff (provable))
+    /// ```
+    GetSort: "get-sort",
+    /// `(declared? x)` returns `'term` if `x` is a term or definition, `'thm` if `x` is an
+    /// axiom or theorem, `'sort` if `x` is a sort, and `#undef` if `x` is not declared.
+    /// This can be used to check whether `add-term!` or `add-thm!` would fail
+    /// because `x` is already declared.
+    Declared: "declared?",
+    /// * `(get-stmts)` returns the list of all global statements in declaration order,
+    ///   as `(kind x)` pairs where `kind` is `'sort` for sorts, `'decl` for terms and
+    ///   theorems, and `'global` for lisp definitions.
+    /// * `(get-stmts kind)` returns only the statements of the given kind.
+    GetStmts: "get-stmts",
+    /// * `(get-spans)` returns the list of objects recorded for hovers and go-to-definition
+    ///   in the current statement so far, as `(kind x sp)` triples, where `sp` is `#undef`
+    ///   annotated with the span of the object, and `kind` and `x` are one of:
+    ///   * `'sort`, `'term`, `'thm`: a reference to the declaration `x`
+    ///   * `'var`: the local variable `x`
+    ///   * `'global`: the lisp global definition `x`
+    ///   * `'expr`, `'proof`: the expression or proof `x`
+    ///   * `'import`: an import of the file `x` (a string)
+    ///   * `'custom`: the hover text `x` added by `annotate-span`
+    ///   * `'goto`: the go-to-definition target added by `annotate-goto`, where `x` is
+    ///     `#undef` annotated with the target span
+    /// * `(get-spans kind)` returns only the objects of the given kind.
+    GetSpans: "get-spans",
+    /// `(get-notation t)` returns the notation information associated to the term `t`,
+    /// or `#undef` if `t` has no notation. The result has the form `(coe? nots)`, where
+    /// `coe?` is `#t` if `t` is used in a coercion, and `nots` is a list with an element
+    /// `(c infx? prec rassoc)` for each constant `c` that produces the term. `infx?` is `#t` for
+    /// infix notations and `#f` for prefix notations, `prec` is the precedence of `c`
+    /// (a number or `'max`), and `rassoc` is `#t` for right associative, `#f` for
+    /// left associative and `#undef` if the notation is not associative.
+    /// ```text
+    /// (get-notation 'wi)  -- (#f (("->" #t 25 #t)))
+    /// ```
+    GetNotation: "get-notation",
+    /// `(get-prec-assoc p)` returns the associativity of the precedence level `p`,
+    /// `'left` or `'right`, or `#undef` if no infix notation has been declared
+    /// at this precedence level.
+    /// ```text
+    /// (get-prec-assoc 25)  -- 'right
+    /// ```
+    GetPrecAssoc: "get-prec-assoc",
+    /// `(add-delimiters ls rs)` adds the characters in the string `ls` as left delimiters
+    /// and the characters in `rs` as right delimiters, like the statement
+    /// `delimiter $ ls $ $ rs $;`. Whitespace characters are ignored. As with the
+    /// `delimiter` statement, this only affects math strings that are parsed after
+    /// the call; formulas that have already been parsed are not retokenized.
+    AddDelimiters: "add-delimiters",
+    /// `(get-coe s1 s2)` returns the coercion from sort `s1` to sort `s2`, as the list of
+    /// term constructors that are applied (innermost first) to coerce an expression,
+    /// or `#undef` if there is no such coercion. If `s1` and `s2` are the same sort
+    /// then the result is the empty list.
+    GetCoe: "get-coe",
+    /// `(apply-coe s1 s2 e)` applies the coercion from sort `s1` to sort `s2` to the
+    /// expression `e`, returning the coerced expression, in the same way that the
+    /// elaborator inserts coercions. It is an error if there is no such coercion.
+    /// If `s1` and `s2` are the same sort then `e` is returned unchanged.
+    ApplyCoe: "apply-coe",
+    /// `(add-coe s1 s2 t)` adds the term `t` as a coercion from sort `s1` to sort `s2`,
+    /// like the statement `coercion t: s1 > s2;`. The term `t` must have exactly one
+    /// argument. It is an error if the new coercion creates a cycle or diamond in the
+    /// coercion graph.
+    AddCoe: "add-coe",
+    /// `(import-env path)` merges the environment of the file at `path` (relative to the
+    /// current file) into the current environment, like an `import` statement. Because
+    /// elaboration of other files happens asynchronously, only files that have already
+    /// been imported by an `import` statement earlier in the current file are available;
+    /// it is an error to call `import-env` on any other file. Conflicting declarations
+    /// are reported as errors at the call site, in the same way as for `import`.
+    ImportEnv: "import-env",
+    /// `(add-prefix! c t prec)` adds the constant string `c` as prefix notation for the
+    /// term `t` at precedence `prec` (a number or `'max`), like the statement
+    /// `prefix t: $c$ prec prec;`.
+    AddPrefix: "add-prefix!",
+    /// `(add-infix! c t prec assoc)` adds the constant string `c` as infix notation for
+    /// the binary term `t` at precedence `prec`, where `assoc` is `'left` or `'right`,
+    /// like the statements `infixl t: $c$ prec prec;` and `infixr t: $c$ prec prec;`.
+    AddInfix: "add-infix!",
+    /// `(add-notation! t prec lits [assoc])` adds a general notation for the term `t`,
+    /// like the `notation` statement. `lits` is a list of literals, each of which is
+    /// either a constant string `"c"`, a constant with a precedence `("c" prec)`, or
+    /// a variable `(i prec)` referring to the `i`th argument of `t`, parsed at
+    /// precedence `prec`. The first constant is declared at the notation precedence
+    /// `prec`, and later constants without a precedence must have been declared already.
+    /// Every argument of `t` must be used. If the notation begins with a variable, it is
+    /// an infix notation and `assoc` (`'left` or `'right`) is required; it is checked
+    /// against the associativity of other infix notations at the same precedence.
+    /// ```text
+    /// (add-notation! 'foo 'max '("[" (0 0) "," (1 0) ("]" max)))
+    /// ```
+    AddNotation: "add-notation!",
+    /// `(expr-deps e)` returns the list of bound variables (including dummy variables)
+    /// that the expression `e` depends on in the current local context. Regular variables
+    /// depend on their declared dependencies, and variables bound by a term constructor
+    /// are not counted. It is an error if `e` contains metavariables.
+    ExprDeps: "expr-deps",
+    /// `(get-term-deps t)` returns the list of bound arguments of the term or definition `t`
+    /// that its return value depends on, in the same format as the dependencies in
+    /// the `ret` component of `get-decl`.
+    /// ```text
+    /// term all {x: set} (ph: wff x): wff;
+    /// (get-term-deps 'all)  -- ()
+    /// term foo {x y: set} (ph: wff x y): wff y;
+    /// (get-term-deps 'foo)  -- (y)
+    /// ```
+    GetTermDeps: "get-term-deps",
+    /// `(unfold-def t args...)` returns the result of substituting the expressions
+    /// `args` into the body of definition `t`. Any dummy variables in the definition
+    /// body are replaced with new metavariables. It is an error if `t` is not a
+    /// definition, or if it is a definition that has no value.
+    /// ```text
+    /// def foo (a b: wff): wff = $ a -> b $;
+    /// (unfold-def 'foo 'x '(not y))  -- (im x (not y))
+    /// ```
+    UnfoldDef: "unfold-def",
+    /// `(unfold! t args...)` is like `(unfold-def t args...)`, but it returns a pair `(e p)`
+    /// where `e` is the unfolded expression and `p` is a conversion proof of `(t args...) = e`,
+    /// suitable for use in a `:conv` proof. The dummy variables in the definition become
+    /// new metavariables in the local context, which will need to be assigned
+    /// before the proof is complete.
+    /// ```text
+    /// def foo (a b: wff): wff = $ a -> b $;
+    /// (unfold! 'foo 'x 'y)  -- ((im x y) (:unfold foo (x y) (im x y)))
+    /// ```
+    UnfoldMut: "unfold!",
+    /// `(mk-refl e)` returns the conversion proof `(:refl e)` of `e = e`.
+    MkRefl: "mk-refl",
+    /// `(mk-cong t c1 ... cn)` returns the conversion proof `(:cong t c1 ... cn)` of
+    /// `(t a1 ... an) = (t b1 ... bn)`, given conversion proofs `ci: ai = bi`.
+    /// It is an error if `t` is not a term constructor with `n` arguments.
+    /// ```text
+    /// (mk-cong 'im (mk-refl 'x) (nth 1 (unfold! 'foo 'y)))
+    /// -- (:cong im (:refl x) (:unfold foo (y) ...))
+    /// ```
+    MkCong: "mk-cong",
+    /// `(conv-sides c)` returns the pair `(lhs rhs)` of expressions for a conversion
+    /// proof `c: lhs = rhs`, such as one constructed by `mk-cong` or `unfold!`.
+    ConvSides: "conv-sides",
+    /// `(subst thm args...)` returns the statement that the theorem `thm` proves
+    /// when applied to the expressions `args`, that is, the result of substituting
+    /// `args` for the variables in the conclusion of `thm`. Theorem statements do not
+    /// contain dummy variables, so no new metavariables are created.
+    /// ```text
+    /// axiom ax_1 (a b: wff): $ a -> b -> a $;
+    /// (subst 'ax_1 'x 'y)  -- (im x (im y x))
+    /// ```
+    Subst: "subst",
     /// `(add-decl! decl-data ...)` adds a new declaration, as if a new `def` or `theorem`
     /// declaration was created. This does not do any elaboration - all information is
     /// expected to be fully elaborated. The input format is the same as the output format
@@ -1023,18 +1550,121 @@ str_enum! {
     /// * `(add-thm! x bis hyps ret)` is the same as `(add-decl! 'axiom x bis hyps ret)`.
     /// * `(add-thm! x bis hyps ret vis vtask)` is the same as
     ///   `(add-decl! 'theorem x bis hyps ret vis vtask)`.
+    /// * `(add-thm! x bis hyps ret vis vtask :unchecked)` adds the theorem without
+    ///   checking (or even evaluating) the proof, as if `(check-proofs #f)` was in effect
+    ///   for this declaration only.
     AddThm: "add-thm!",
+    /// * `(verify-proof bis hyps ret proof)` checks that `proof` is a valid proof of
+    ///   the statement given by `bis hyps ret`, in the same format as `add-thm!`, and
+    ///   returns `#t`. Nothing is added to the environment. `proof` should be a
+    ///   `(ds proof)` pair, as would be returned by the `vtask` argument of `add-thm!`.
+    ///   If the check fails, this raises an error, which can be caught with `try`.
+    /// * `(verify-proof bis ret proof)` is the same, with no hypotheses.
+    VerifyProof: "verify-proof",
+    /// `(proof-stat bis hyps ret proof)` checks a proof in the same way as `verify-proof`,
+    /// and returns a list `(total shared ratio)`, where `total` is the number of distinct
+    /// subterms of the statement and proof after deduplication, `shared` is the number of
+    /// them that are used more than once, and `ratio` is `shared / total`.
+    ProofStat: "proof-stat",
+    /// `(prepare-stmt bis hyps ret)` elaborates the theorem statement given by `bis`,
+    /// `hyps` and `ret` (in the same format as `verify-proof`, and `hyps` may be omitted),
+    /// and returns a handle (a number) that can be passed to `check-prepared`. This avoids
+    /// repeating the analysis of the statement when checking many proofs against it.
+    /// Handles are only valid in the file in which they were created, and the statement
+    /// is kept until the end of that file. A handle created inside `with-env-snapshot`
+    /// becomes invalid if the snapshot is rolled back. Handles are never reused, so
+    /// an invalid handle is always reported as an error.
+    PrepareStmt: "prepare-stmt",
+    /// `(check-prepared h proof)` checks `proof`, which has the form `(ds proof)`, against
+    /// the statement with handle `h` returned by `prepare-stmt`, in the same way as
+    /// `verify-proof`. It returns `#t` if the proof is correct and raises an error otherwise.
+    CheckPrepared: "check-prepared",
+    /// `(dump-proof-dag x)` prints the stored proof of theorem `x` in its deduplicated form,
+    /// as the list of heap elements `#n = e`, followed by the hypotheses and the proof
+    /// itself, where `#n` in an expression is a reference to heap element `n`. This shows
+    /// the sharing in the proof, which is lost when the proof is accessed via `get-decl`.
+    DumpProofDag: "dump-proof-dag",
+    /// `(proof-size x)` returns a list `(heap-len nodes)` describing the size of the stored
+    /// proof of theorem `x`, where `heap-len` is the number of entries in the deduplicated
+    /// proof heap (including the theorem variables) and `nodes` is the total number of
+    /// proof nodes, counting each reference to a shared heap entry as one node.
+    ProofSize: "proof-size",
+    /// `(export-mmu)` returns a string containing the current environment (all declarations
+    /// so far) in MMU format, the same as the output of `mm0-rs compile` on the file up
+    /// to this point. It is an error if some theorem so far has a proof that failed to check.
+    ExportMmu: "export-mmu",
+    /// `(export-decl-mmu x)` returns a string containing the declaration of the sort, term,
+    /// or theorem `x` in MMU format, as it would appear in the output of `export-mmu`.
+    ExportDeclMmu: "export-decl-mmu",
+    /// `(check-dv thm args...)` checks whether applying theorem `thm` to the expressions
+    /// `args` would satisfy the disjoint variable conditions of `thm`, using the variables
+    /// in the local context. It returns `#t` if there is no violation, and otherwise the
+    /// list of pairs `(x y)` of variables of `thm` whose substitutions share a bound variable.
+    CheckDv: "check-dv",
+    /// `(with-namespace ns f)` calls the thunk `f` with no arguments, and while it is
+    /// running, the string `ns` is prepended to the names of all declarations added
+    /// using `add-decl!`, `add-term!` and `add-thm!`. For example, inside
+    /// `(with-namespace "foo." f)`, `(add-thm! 'bar ...)` adds a theorem named `foo.bar`.
+    WithNamespace: "with-namespace",
+    /// `(open-namespace ns f)` calls the thunk `f` with no arguments, and while it is
+    /// running, names `x` that are not otherwise defined are resolved to the name `nsx`
+    /// (the string `ns` prepended to `x`), if it exists. This applies to global lisp
+    /// definitions and theorems used in `refine`. For example, inside
+    /// `(open-namespace "foo." f)`, `(refine '(bar))` can be used to apply theorem `foo.bar`.
+    OpenNamespace: "open-namespace",
+    /// `(with-env-snapshot f)` calls the thunk `f` with no arguments, and returns its result.
+    /// If `f` returns the atom `rollback` or raises an error, all sorts, terms and theorems
+    /// added by `f` are removed, and notations and coercions are reset to their state
+    /// before the call. Atoms interned by `f`, lisp global definitions, and
+    /// changes to the local context are not rolled back. Taking the snapshot copies
+    /// all notations and coercions, so this should not be used in a tight loop.
+    WithEnvSnapshot: "with-env-snapshot",
     /// * `(dummy! x s)` produces a new dummy variable called `x` with sort `s`, and returns `x`;
     /// * `(dummy! s)` automatically gives the variable a name like `_123` that is guaranteed to be unused.
     NewDummy: "dummy!",
+    /// `(dummy? x)` is true if `x` is the name of a dummy variable in the local context,
+    /// that is, a variable that is internal to the current proof or definition, rather
+    /// than one of the variables in the statement.
+    IsDummy: "dummy?",
+    /// `(must-bound? x)` returns `#t` if `x` is a local variable whose sort has not yet
+    /// been determined, but which is known to be a bound variable because it appears in
+    /// a position that requires one, and `#f` if it is not known to be bound.
+    /// It returns `#undef` if `x` is not a local variable or its sort is already known.
+    MustBound: "must-bound?",
+    /// `(get-dummies)` returns the list of dummy variables in the local context,
+    /// as a list of `(x s)` pairs where `s` is the sort of `x`, or `#undef` if the
+    /// sort has not been determined yet.
+    GetDummies: "get-dummies",
+    /// `(max-bound-vars)` returns the maximum number of bound variables (including
+    /// dummy variables) that a single declaration may have.
+    MaxBoundVars: "max-bound-vars",
     /// `(check-proofs b)` turns on (`b = #t`) or off (`b = #f`) proof checking for theorems.
     CheckProofs: "check-proofs",
+    /// `(set-name-policy f)` sets the naming policy to the predicate `f`. After this,
+    /// `(f x)` is called on the name `x` of every new sort, term and theorem, and a warning
+    /// is reported if it returns false. `(set-name-policy #undef)` removes the naming policy.
+    SetNamePolicy: "set-name-policy",
+    /// * `(deprecate thm msg)` marks the theorem `thm` as deprecated, so that every
+    ///   subsequent use of `thm` in `refine` produces a warning containing the string `msg`.
+    /// * `(deprecate thm)` is the same, but with no message.
+    Deprecate: "deprecate",
     /// * `(set-reporting type b)` turns on (`b = #t`) or off (`b = #f`)
     ///   error reporting for error type `type`, which can be `'error`, `'info` or `'warn`.
     ///   (Compilation will still be aborted if there are errors, even if the
     ///   display is suppressed.)
     /// * `(set-reporting b)` will set the error reporting to `b` for all error types.
     SetReporting: "set-reporting",
+    /// `(with-reporting mode f)` calls the thunk `f` with no arguments with the error
+    /// reporting mode temporarily set to `mode`, and restores the previous mode
+    /// afterward, even if `f` fails with an error. `mode` is either a boolean,
+    /// which turns all error types on or off, or a list of the error types
+    /// (`'error`, `'info` or `'warn`) that should be reported.
+    WithReporting: "with-reporting",
+    /// `(set-report-dedup b)` turns on (`b = #t`) or off (`b = #f`) deduplication of
+    /// reported messages. When it is on, a message with the same span, error type and
+    /// text as a message already reported in the current statement is dropped.
+    /// This is off by default.
+    SetReportDedup: "set-report-dedup",
     /// `refine-extra-args` can be called directly, but it simply returns an error. It is called
     /// by `refine` when elaborating a term with too many arguments, and is expected to be
     /// overridden by user code to provide a more useful behavior.
@@ -1047,6 +1677,16 @@ str_enum! {
   }
 }
 
+impl BuiltinProc {
+  /// Returns true if this builtin is a tactic, that is, it operates on the current
+  /// proof state. Calls to these builtins are captured by `start-recording`.
+  pub fn is_tactic(self) -> bool {
+    matches!(self, BuiltinProc::Refine | BuiltinProc::Have | BuiltinProc::SetGoals |
+      BuiltinProc::Symm | BuiltinProc::Trans | BuiltinProc::RotateGoals | BuiltinProc::SwapGoals |
+      BuiltinProc::ClearHyp)
+  }
+}
+
 impl std::fmt::Display for BuiltinProc {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     self.to_str().fmt(f)
@@ -7,21 +7,24 @@
 //! [`IR`]: ../parser/enum.IR.html
 
 use std::ops::{Deref, DerefMut};
-use std::mem;
+use std::{cmp, mem};
+use std::path::PathBuf;
 use std::time::{Instant, Duration};
 use std::sync::atomic::Ordering;
-use std::collections::HashMap;
-use num::{BigInt, ToPrimitive};
+use std::collections::{HashMap, HashSet};
+use num::{BigInt, BigRational, ToPrimitive, Zero, One, Signed};
 use crate::util::*;
 use crate::parser::ast::SExpr;
-use super::super::{Result, Elaborator,
+use super::super::{Result, Elaborator, FrozenEnv,
   AtomID, Environment, AtomData, DeclKey, StmtTrace,
-  ElabError, ElabErrorKind, ErrorLevel, BoxError, ObjectKind,
+  ElabError, ElabErrorKind, ErrorLevel, BoxError, ObjectKind, ReportMode, LocalContext,
   refine::{RStack, RState, RefineResult}};
 use super::*;
 use super::parser::{IR, Branch, Pattern};
-use super::super::local_context::{InferSort, AwaitingProof, try_get_span};
-use super::super::environment::{ExprNode, ProofNode};
+use super::super::local_context::{InferSort, AwaitingProof, try_get_span, MAX_BOUND_VARS};
+use super::super::environment::{ExprNode, ProofNode, Term, TermID, Type, Prec, NotaInfo, Literal,
+  EnvSnapshot};
+use super::super::proof::{Subst, dv_pairs};
 use super::print::{FormatEnv, EnvDisplay};
 
 #[derive(Debug)]
@@ -47,6 +50,20 @@ enum Stack<'a> {
   Refine {sp: Span, stack: Vec<RStack>},
   Focus(Span, bool, Vec<LispVal>),
   Have(Span, LispVal),
+  Replay(Span, std::vec::IntoIter<LispVal>),
+  Record(LispVal),
+  StepBudget(Option<usize>, usize),
+  RestoreReporting(ReportMode),
+  GoalsClosed(Vec<LispVal>),
+  MadeProgress(Vec<(LispVal, Option<LispVal>)>, (usize, usize)),
+  Now(Span, Vec<LispVal>),
+  FocusOn(Span, LispVal, std::vec::IntoIter<LispVal>, usize),
+  FocusOnTacs(Span, std::vec::IntoIter<LispVal>),
+  Solve(Box<LocalContext>, Vec<(LispVal, LispVal)>),
+  Try(Box<LocalContext>, Vec<(LispVal, LispVal)>),
+  RestoreNamespace(Option<ArcString>),
+  CloseNamespace(usize),
+  EnvSnapshot(Box<EnvSnapshot>, usize),
 }
 
 impl<'a> EnvDisplay for Stack<'a> {
@@ -83,6 +100,20 @@ impl<'a> EnvDisplay for Stack<'a> {
       Stack::Refine {..} => write!(f, "(refine _)"),
       &Stack::Focus(_, cl, ref es) => write!(f, "(focus {} _)\n  ->{}", cl, fe.to(es)),
       Stack::Have(_, a) => write!(f, "(have {} _)", fe.to(a)),
+      Stack::Replay(_, es) => write!(f, "(replay _ {})", fe.to(es.as_slice())),
+      Stack::Record(e) => write!(f, "(record {})", fe.to(e)),
+      &Stack::StepBudget(_, n) => write!(f, "(with-step-budget {} _)", n),
+      Stack::RestoreReporting(_) => write!(f, "(with-reporting _)"),
+      Stack::GoalsClosed(gs) => write!(f, "(goals-closed-by _)\n  ->{}", fe.to(gs)),
+      Stack::MadeProgress(_, _) => write!(f, "(made-progress? _)"),
+      Stack::Now(_, es) => write!(f, "(now _)\n  ->{}", fe.to(es)),
+      Stack::FocusOn(_, p, es, _) => write!(f, "(focus-on {} {})", fe.to(p), fe.to(es.as_slice())),
+      Stack::FocusOnTacs(_, es) => write!(f, "(focus-on _ {})", fe.to(es.as_slice())),
+      Stack::Solve(_, _) => write!(f, "(solve _)"),
+      Stack::Try(_, _) => write!(f, "(try _)"),
+      Stack::RestoreNamespace(_) => write!(f, "(with-namespace _)"),
+      Stack::CloseNamespace(_) => write!(f, "(open-namespace _)"),
+      Stack::EnvSnapshot(_, _) => write!(f, "(with-env-snapshot _)"),
     }
   }
 }
@@ -210,6 +241,9 @@ struct TestPending<'a>(Span, LispVal, &'a IR);
 /// work without an elaboration context.
 pub type SResult<T> = std::result::Result<T, String>;
 
+/// The maximum number of materialized proofs kept in `Elaborator::proof_cache`.
+const PROOF_CACHE_SIZE: usize = 1024;
+
 impl Elaborator {
   fn pattern_match<'b>(&mut self, stack: &mut Vec<PatternStack<'b>>, ctx: &mut [LispVal],
       mut active: PatternState<'b>) -> std::result::Result<bool, TestPending<'b>> {
@@ -368,12 +402,29 @@ impl Elaborator {
     })
   }
 
+  fn as_prec(&self, e: &LispVal) -> SResult<Prec> {
+    e.unwrapped(|e| match e {
+      LispKind::Number(n) => n.to_u32().map(Prec::Prec)
+        .ok_or_else(|| format!("precedence out of range: {}", n)),
+      &LispKind::Atom(AtomID::MAX) => Ok(Prec::Max),
+      _ => Err(format!("expected a precedence, got {}", self.print(e)))
+    })
+  }
+
   fn as_int(&self, e: &LispVal) -> SResult<BigInt> {
     e.unwrapped(|e| if let LispKind::Number(n) = e {Ok(n.clone())} else {
       Err(format!("expected a integer, got {}", self.print(e)))
     })
   }
 
+  fn as_ratio(&self, e: &LispVal) -> SResult<BigRational> {
+    e.unwrapped(|e| match e {
+      LispKind::Number(n) => Ok(BigRational::from_integer(n.clone())),
+      LispKind::Ratio(q) => Ok(q.clone()),
+      _ => Err(format!("expected a number, got {}", self.print(e)))
+    })
+  }
+
   fn as_ref<T>(&self, e: &LispKind, f: impl FnOnce(&mut LispVal) -> SResult<T>) -> SResult<T> {
     e.as_ref_(f).unwrap_or_else(|| Err(format!("not a ref-cell: {}", self.print(e))))
   }
@@ -385,6 +436,26 @@ impl Elaborator {
     })
   }
 
+  fn format(&self, fmt: &str, args: &[LispVal]) -> SResult<String> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut it = fmt.chars().peekable();
+    while let Some(c) = it.next() {
+      match (c, it.peek().copied()) {
+        ('{', Some('{')) | ('}', Some('}')) => {it.next(); out.push(c)}
+        ('{', Some('}')) => {
+          it.next();
+          let e = args.next().ok_or("format: too few arguments")?;
+          out.push_str(&self.to_string(e))
+        }
+        ('{', _) | ('}', _) => return Err("format: unmatched brace".into()),
+        _ => out.push(c)
+      }
+    }
+    if args.next().is_some() {return Err("format: too many arguments".into())}
+    Ok(out)
+  }
+
   fn to_string(&self, e: &LispKind) -> ArcString {
     match e {
       LispKind::Ref(m) => self.to_string(&m.get()),
@@ -396,15 +467,26 @@ impl Elaborator {
     }
   }
 
-  fn int_bool_binop(&self, mut f: impl FnMut(&BigInt, &BigInt) -> bool, args: &[LispVal]) -> SResult<bool> {
-    let mut it = args.iter();
-    let mut last = self.as_int(it.next().unwrap())?;
-    for v in it {
-      let new = self.as_int(v)?;
-      if !f(&last, &new) {return Ok(false)}
-      last = new;
+  /// Compare each adjacent pair of numbers in `args`, and return true if `f` returns
+  /// true on all the comparisons. The numbers are compared as rationals if any of them
+  /// is a ratio, and as integers otherwise.
+  fn num_bool_binop(&self, f: impl Fn(cmp::Ordering) -> bool, args: &[LispVal]) -> SResult<bool> {
+    fn go<T: Ord>(args: &[LispVal], as_num: impl Fn(&LispVal) -> SResult<T>,
+        f: impl Fn(cmp::Ordering) -> bool) -> SResult<bool> {
+      let mut it = args.iter();
+      let mut last = as_num(it.next().unwrap())?;
+      for v in it {
+        let new = as_num(v)?;
+        if !f(last.cmp(&new)) {return Ok(false)}
+        last = new;
+      }
+      Ok(true)
+    }
+    if args.iter().any(|e| e.is_ratio()) {
+      go(args, |e| self.as_ratio(e), f)
+    } else {
+      go(args, |e| self.as_int(e), f)
     }
-    Ok(true)
   }
 
   fn stat(&self) -> String {
@@ -421,6 +503,471 @@ impl Elaborator {
     s
   }
 
+  /// Render the current proof state as JSON, for `export-state`.
+  fn export_state(&self, fsp: &FileSpan) -> String {
+    use std::fmt::Write;
+    fn string(s: &mut String, t: &str) {
+      s.push('"');
+      for c in t.chars() {
+        match c {
+          '"' => s.push_str("\\\""),
+          '\\' => s.push_str("\\\\"),
+          '\n' => s.push_str("\\n"),
+          '\r' => s.push_str("\\r"),
+          '\t' => s.push_str("\\t"),
+          c if (c as u32) < 0x20 => s.push_str(&format!("\\u{:04x}", c as u32)),
+          c => s.push(c),
+        }
+      }
+      s.push('"')
+    }
+    let entry = |s: &mut String, name: Option<AtomID>, e: &LispVal| {
+      s.push('{');
+      if let Some(a) = name {
+        s.push_str("\"name\":");
+        string(s, &self.data[a].name);
+        s.push(',')
+      }
+      s.push_str("\"type\":");
+      string(s, &format!("{}", self.format_env().pp(e, 80)));
+      let sp = try_get_span(fsp, e);
+      write!(s, ",\"span\":[{},{}]}}", sp.start, sp.end).unwrap()
+    };
+    let mut s = String::from("{\"goals\":[");
+    let mut first = true;
+    for g in &self.lc.goals {
+      g.unwrapped(|r| if let LispKind::Goal(e) = r {
+        if !mem::take(&mut first) {s.push(',')}
+        entry(&mut s, None, e)
+      })
+    }
+    s.push_str("],\"hyps\":[");
+    for (i, (a, e, _)) in self.lc.proof_order.iter().enumerate() {
+      if i != 0 {s.push(',')}
+      entry(&mut s, Some(*a), e)
+    }
+    s.push_str("]}");
+    s
+  }
+
+  /// Print a proof node in a compact notation, where `#n` is a reference to heap element `n`.
+  fn write_proof_node(&self, out: &mut String, e: &ProofNode) {
+    use std::fmt::Write;
+    let list = |out: &mut String, es: &[ProofNode]| for e in es {
+      out.push(' ');
+      self.write_proof_node(out, e)
+    };
+    match e {
+      &ProofNode::Ref(n) => write!(out, "#{}", n).unwrap(),
+      &ProofNode::Dummy(a, s) =>
+        write!(out, "{{{}: {}}}", self.print(&a), self.print(&self.sorts[s].atom)).unwrap(),
+      ProofNode::Term {term, args} => {
+        write!(out, "({}", self.print(&self.terms[*term].atom)).unwrap();
+        list(out, args);
+        out.push(')')
+      }
+      ProofNode::Hyp(i, e) => {
+        write!(out, "(:hyp {} ", i).unwrap();
+        self.write_proof_node(out, e);
+        out.push(')')
+      }
+      ProofNode::Thm {thm, args, res} => {
+        write!(out, "({}", self.print(&self.thms[*thm].atom)).unwrap();
+        list(out, args);
+        out.push_str(" : ");
+        self.write_proof_node(out, res);
+        out.push(')')
+      }
+      ProofNode::Conv(p) => {
+        out.push_str("(:conv");
+        for e in &[&p.0, &p.1, &p.2] {
+          out.push(' ');
+          self.write_proof_node(out, e)
+        }
+        out.push(')')
+      }
+      ProofNode::Refl(e) => {
+        out.push_str("(:refl ");
+        self.write_proof_node(out, e);
+        out.push(')')
+      }
+      ProofNode::Sym(e) => {
+        out.push_str("(:sym ");
+        self.write_proof_node(out, e);
+        out.push(')')
+      }
+      ProofNode::Cong {term, args} => {
+        write!(out, "(:cong {}", self.print(&self.terms[*term].atom)).unwrap();
+        list(out, args);
+        out.push(')')
+      }
+      ProofNode::Unfold {term, args, res} => {
+        write!(out, "(:unfold {} (", self.print(&self.terms[*term].atom)).unwrap();
+        for (i, e) in args.iter().enumerate() {
+          if i != 0 {out.push(' ')}
+          self.write_proof_node(out, e)
+        }
+        out.push(')');
+        for e in &[&res.0, &res.1, &res.2] {
+          out.push(' ');
+          self.write_proof_node(out, e)
+        }
+        out.push(')')
+      }
+    }
+  }
+
+  /// Get the size of the stored proof of theorem `t`, as a pair `(heap_len, nodes)` where
+  /// `heap_len` is the length of the proof heap and `nodes` is the total number of proof
+  /// nodes in the heap, the hypotheses and the proof itself, where each heap reference
+  /// counts as one node. Returns `None` if `t` has no (successfully checked) proof.
+  fn proof_size(&self, t: ThmID) -> Option<(usize, usize)> {
+    fn size(e: &ProofNode) -> usize {
+      1 + match e {
+        ProofNode::Ref(_) | ProofNode::Dummy(_, _) => 0,
+        ProofNode::Term {args, ..} | ProofNode::Cong {args, ..} => args.iter().map(size).sum(),
+        ProofNode::Hyp(_, e) | ProofNode::Refl(e) | ProofNode::Sym(e) => size(e),
+        ProofNode::Thm {args, res, ..} => args.iter().map(size).sum::<usize>() + size(res),
+        ProofNode::Conv(p) => size(&p.0) + size(&p.1) + size(&p.2),
+        ProofNode::Unfold {args, res, ..} =>
+          args.iter().map(size).sum::<usize>() + size(&res.0) + size(&res.1) + size(&res.2),
+      }
+    }
+    let td = &self.thms[t];
+    let pr = td.proof.as_ref()?.as_ref()?;
+    let nodes = pr.heap[td.args.len()..].iter().chain(&pr.hyps).map(size).sum::<usize>() +
+      size(&pr.head);
+    Some((pr.heap.len(), nodes))
+  }
+
+  /// Print the stored proof of theorem `t` as a DAG, showing each heap element
+  /// with its index. Returns `None` if `t` has no (successfully checked) proof.
+  fn dump_proof_dag(&self, t: ThmID) -> Option<String> {
+    use std::fmt::Write;
+    let td = &self.thms[t];
+    let pr = td.proof.as_ref()?.as_ref()?;
+    let mut s = String::new();
+    for (i, (a, _)) in td.args.iter().enumerate() {
+      writeln!(s, "#{} = {}", i, self.print(&a.unwrap_or(AtomID::UNDER))).unwrap()
+    }
+    for (i, e) in pr.heap.iter().enumerate().skip(td.args.len()) {
+      write!(s, "#{} = ", i).unwrap();
+      self.write_proof_node(&mut s, e);
+      s.push('\n')
+    }
+    for (i, e) in pr.hyps.iter().enumerate() {
+      write!(s, "hyp {}: ", i).unwrap();
+      self.write_proof_node(&mut s, e);
+      s.push('\n')
+    }
+    s.push_str("proof: ");
+    self.write_proof_node(&mut s, &pr.head);
+    Some(s)
+  }
+
+  /// Get the spans collected so far in the current statement, for `get-spans`.
+  fn get_spans(&self, filter: Option<AtomID>) -> LispVal {
+    LispVal::list((&self.spans).into_iter().filter_map(|(sp, k)| {
+      let (k, v) = match k {
+        &ObjectKind::Sort(s) => (AtomID::SORT, LispVal::atom(self.sorts[s].atom)),
+        &ObjectKind::Term(t, _) => (AtomID::TERM, LispVal::atom(self.terms[t].atom)),
+        &ObjectKind::Thm(t) => (AtomID::THM_DECL, LispVal::atom(self.thms[t].atom)),
+        &ObjectKind::Var(a) => (AtomID::VAR, LispVal::atom(a)),
+        &ObjectKind::Global(a) => (AtomID::GLOBAL, LispVal::atom(a)),
+        // Safety: we are still elaborating, so these values are not shared with other threads
+        ObjectKind::Expr(e) => (AtomID::EXPR, unsafe { e.thaw() }.clone()),
+        ObjectKind::Proof(e) => (AtomID::PROOF, unsafe { e.thaw() }.clone()),
+        ObjectKind::Import(f) => (AtomID::IMPORT, LispVal::string(f.rel().into())),
+        ObjectKind::Custom(s) => (AtomID::CUSTOM, LispVal::string(s.clone())),
+        ObjectKind::Goto(fsp) => (AtomID::GOTO, LispVal::undef().span(fsp.clone())),
+      };
+      if filter.map_or(true, |f| f == k) {
+        let fsp = FileSpan {file: self.path.clone(), span: *sp};
+        Some(LispVal::list(vec![LispVal::atom(k), v, LispVal::undef().span(fsp)]))
+      } else {None}
+    }).collect::<Vec<_>>())
+  }
+
+  /// Find the `FileRef` for the current file or one of the files imported by it,
+  /// given its path relative to the project root, for `make-span`.
+  fn find_file(&self, rel: &str) -> Option<FileRef> {
+    if self.path.rel() == rel { return Some(self.path.clone()) }
+    self.env.spans.iter().flatten().find_map(|(_, k)| match k {
+      ObjectKind::Import(f) if f.rel() == rel => Some(f.clone()),
+      _ => None
+    })
+  }
+
+  fn get_stat(&self) -> LispVal {
+    let hyps = self.lc.proof_order.iter()
+      .map(|(a, e, _)| LispVal::list(vec![LispVal::atom(*a), e.clone()])).collect::<Vec<_>>();
+    let goals = self.lc.goals.iter().filter_map(|e| e.goal_type()).collect::<Vec<_>>();
+    LispVal::list(vec![LispVal::list(hyps), LispVal::list(goals)])
+  }
+
+  /// Get the head term of the first goal, which should be a relation application
+  /// like `|- (eq a b)`.
+  fn rel_goal(&self) -> SResult<TermID> {
+    let ty = self.lc.goals.first().and_then(|g| g.goal_type()).ok_or("no goals")?;
+    ty.head().and_then(|e| e.as_atom()).and_then(|a| self.term(a))
+      .filter(|&t| self.terms[t].args.len() == 2)
+      .ok_or_else(|| format!("expected a goal of the form (eq a b), got {}", self.print(&ty)))
+  }
+
+  /// Find a theorem whose hypotheses and conclusion are all applications of the
+  /// binary term `t` to variables, in the pattern given by `hyps` and `concl`.
+  /// The pattern variables are numbered `0..n`, and the theorem must have exactly
+  /// `n` arguments. Returns the theorem and the map from pattern variables to
+  /// the theorem's argument indexes.
+  fn find_rel_lemma(&self, t: TermID,
+      hyps: &[(usize, usize)], concl: (usize, usize)) -> Option<(ThmID, Vec<usize>)> {
+    fn rel_args(heap: &[ExprNode], nargs: usize, t: TermID, e: &ExprNode) -> Option<(usize, usize)> {
+      match *e {
+        ExprNode::Ref(i) if i >= nargs => rel_args(heap, nargs, t, &heap[i]),
+        ExprNode::App(t2, ref es) if t2 == t && es.len() == 2 => match (&es[0], &es[1]) {
+          (&ExprNode::Ref(i), &ExprNode::Ref(j)) if i < nargs && j < nargs => Some((i, j)),
+          _ => None
+        },
+        _ => None
+      }
+    }
+    fn assign(map: &mut [Option<usize>], a: usize, i: usize) -> bool {
+      match map[a] {
+        Some(j) => i == j,
+        None if map.contains(&Some(i)) => false,
+        None => {map[a] = Some(i); true}
+      }
+    }
+    let n = hyps.iter().chain(std::iter::once(&concl))
+      .map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+    'thms: for (i, td) in self.thms.iter().enumerate() {
+      if td.args.len() != n || td.hyps.len() != hyps.len() {continue}
+      let mut map = vec![None; n];
+      let pats = hyps.iter().zip(td.hyps.iter().map(|(_, h)| h))
+        .chain(std::iter::once((&concl, &td.ret)));
+      for (&(a, b), e) in pats {
+        match rel_args(&td.heap, n, t, e) {
+          Some((x, y)) if assign(&mut map, a, x) && assign(&mut map, b, y) => {}
+          _ => continue 'thms
+        }
+      }
+      return Some((ThmID(i as u32), map.into_iter().map(Option::unwrap).collect()))
+    }
+    None
+  }
+
+  /// Find the first position at which `e1` and `e2` differ, pushing the argument
+  /// indexes that lead there onto `path`, and return the two differing subterms.
+  /// Returns `None` if the expressions are equal.
+  fn expr_diff(e1: &LispVal, e2: &LispVal, path: &mut Vec<usize>) -> Option<(LispVal, LispVal)> {
+    let (e1, e2) = (e1.unwrapped_arc(), e2.unwrapped_arc());
+    if e1.at_least(1) && e2.at_least(1) && e1.len() == e2.len() {
+      let mut u1 = Uncons::from(e1.clone());
+      let mut u2 = Uncons::from(e2.clone());
+      if u1.next() == u2.next() {
+        for (i, (a1, a2)) in u1.zip(u2).enumerate() {
+          path.push(i + 1);
+          if let Some(r) = Self::expr_diff(&a1, &a2, path) {return Some(r)}
+          path.pop();
+        }
+        return None
+      }
+    }
+    if e1 == e2 {None} else {Some((e1, e2))}
+  }
+
+  /// Print `e`, marking the subterm at position `path` (as produced by `expr_diff`) with `«»`.
+  fn write_marked(&self, out: &mut String, e: &LispVal, path: &[usize]) {
+    use std::fmt::Write;
+    match path.split_first() {
+      None => write!(out, "«{}»", self.print(e)).unwrap(),
+      Some((&n, path)) => {
+        out.push('(');
+        for (i, e) in Uncons::from(e.clone()).enumerate() {
+          if i != 0 {out.push(' ')}
+          if i == n {self.write_marked(out, &e, path)}
+          else {write!(out, "{}", self.print(&e)).unwrap()}
+        }
+        out.push(')');
+      }
+    }
+  }
+
+  /// Match the expression `e` against the pattern `pat` in the context of
+  /// a theorem with heap `heap` and `args.len()` arguments, assigning the arguments as
+  /// they are encountered. The atom `_` in `e` is treated as a wildcard.
+  fn match_expr(&self, heap: &[ExprNode], args: &mut [Option<LispVal>],
+      pat: &ExprNode, e: &LispVal) -> bool {
+    if e.as_atom() == Some(AtomID::UNDER) {return true}
+    match *pat {
+      ExprNode::Ref(i) if i >= args.len() => self.match_expr(heap, args, &heap[i], e),
+      ExprNode::Ref(i) => match &args[i] {
+        Some(v) => v == e,
+        None => {args[i] = Some(e.clone()); true}
+      },
+      ExprNode::Dummy(_, _) => false,
+      ExprNode::App(t, ref es) => {
+        let mut u = Uncons::from(e.clone());
+        u.next().and_then(|h| h.as_atom()) == Some(self.terms[t].atom) &&
+        es.iter().all(|p| u.next().map_or(false, |e| self.match_expr(heap, args, p, &e))) &&
+        u.exactly(0)
+      }
+    }
+  }
+
+  /// The shallow search used by `provable?`. Returns true if `e` is a hypothesis,
+  /// or the first theorem in `thms` whose conclusion matches `e` has hypotheses that are
+  /// all (recursively) provable with depth `depth - 1`.
+  fn shallow_provable(&self, thms: &[ThmID], e: &LispVal, depth: usize) -> bool {
+    if e.as_atom() == Some(AtomID::UNDER) ||
+      self.lc.proof_order.iter().any(|(_, ty, _)| ty == e) {return true}
+    if depth == 0 {return false}
+    for &t in thms {
+      let td = &self.thms[t];
+      let mut args = vec![None; td.args.len()];
+      if self.match_expr(&td.heap, &mut args, &td.ret, e) {
+        let args = args.into_iter()
+          .map(|a| a.unwrap_or_else(|| LispVal::atom(AtomID::UNDER))).collect();
+        let mut subst = Subst::new(&self.env, &td.heap, args);
+        return td.hyps.iter().all(|(_, h)| match subst.subst(h) {
+          Ok(h) => self.shallow_provable(thms, &h, depth - 1),
+          Err(_) => false,
+        })
+      }
+    }
+    false
+  }
+
+  fn unfold_def(&mut self, t: TermID, args: Vec<LispVal>) -> SResult<LispVal> {
+    let tdata = &self.env.terms[t];
+    if args.len() != tdata.args.len() {
+      return Err(format!("expected {} arguments", tdata.args.len()))
+    }
+    match &tdata.val {
+      None => Err(format!("'{}' is a term, not a definition", self.print(&tdata.atom))),
+      Some(None) => Err(format!("definition '{}' has no value", self.print(&tdata.atom))),
+      Some(Some(val)) => Ok(Subst::new(&self.env, &val.heap, args).subst_mut(&mut self.lc, &val.head)?),
+    }
+  }
+
+  /// Compute the dependencies of the value of a definition, as a bitmask over the bound
+  /// variables of the definition, followed by the dummy variables in order of appearance.
+  /// Returns `None` if `td` has no value.
+  fn infer_def_deps(&self, td: &Term) -> Option<u64> {
+    fn deps(env: &Environment, vals: &[u64], dummies: &mut HashMap<AtomID, u64>,
+        next: &mut u32, e: &ExprNode) -> u64 {
+      match *e {
+        ExprNode::Ref(i) => vals[i],
+        ExprNode::Dummy(a, _) => *dummies.entry(a).or_insert_with(|| {
+          let n = 1u64.checked_shl(*next).unwrap_or(0);
+          *next += 1;
+          n
+        }),
+        ExprNode::App(t, ref es) => {
+          let tdef = &env.terms[t];
+          let mut argbv = Vec::new();
+          let mut out = 0;
+          for (&(_, ty), e) in tdef.args.iter().zip(es) {
+            let mut n = deps(env, vals, dummies, next, e);
+            match ty {
+              Type::Bound(_) => argbv.push(n),
+              Type::Reg(_, ds) => {
+                for (i, &arg) in argbv.iter().enumerate() {
+                  if ds & (1 << i) != 0 { n &= !arg }
+                }
+                out |= n;
+              }
+            }
+          }
+          for (i, arg) in argbv.into_iter().enumerate() {
+            if tdef.ret.1 & (1 << i) != 0 { out |= arg }
+          }
+          out
+        }
+      }
+    }
+    let val = td.val.as_ref()?.as_ref()?;
+    let mut vals = Vec::with_capacity(val.heap.len());
+    let mut next = 0;
+    for &(_, ty) in &td.args {
+      vals.push(match ty {
+        Type::Bound(_) => {next += 1; 1 << (next - 1)}
+        Type::Reg(_, ds) => ds,
+      })
+    }
+    let mut dummies = HashMap::new();
+    for e in &val.heap[td.args.len()..] {
+      let n = deps(&self.env, &vals, &mut dummies, &mut next, e);
+      vals.push(n)
+    }
+    Some(deps(&self.env, &vals, &mut dummies, &mut next, &val.head))
+  }
+
+  /// Get the definitions whose value depends on variables that are not in the
+  /// declared dependencies of the definition.
+  fn audit_deps(&self) -> Vec<TermID> {
+    self.terms.iter().enumerate()
+      .filter(|(_, td)| self.infer_def_deps(td).map_or(false, |n| n & !td.ret.1 != 0))
+      .map(|(i, _)| TermID(i as u32)).collect()
+  }
+
+  /// Get the definitions that are not marked `local` and are not used by any other declaration.
+  fn unused_defs(&self) -> Vec<TermID> {
+    let mut used = vec![false; self.terms.len()];
+    for td in self.terms.iter() {
+      td.visit_deps(&mut |t| used[t.0 as usize] = true)
+    }
+    for td in self.thms.iter() {
+      td.visit_deps(&mut |d| if let DeclKey::Term(t) = d {used[t.0 as usize] = true})
+    }
+    self.terms.iter().enumerate()
+      .filter(|&(i, td)| !used[i] && td.val.is_some() && !td.vis.contains(Modifiers::LOCAL))
+      .map(|(i, _)| TermID(i as u32)).collect()
+  }
+
+  /// Get the theorems whose conclusion is an application of `t`, first adding
+  /// any theorems that were declared since the last query to the index.
+  fn thms_by_head(&mut self, t: TermID) -> &[ThmID] {
+    let (n, index) = &mut self.thm_heads;
+    for (i, td) in self.env.thms.iter().enumerate().skip(*n) {
+      let e = match td.ret {
+        ExprNode::Ref(j) => &td.heap[j],
+        ref e => e,
+      };
+      if let ExprNode::App(t, _) = *e {
+        index.entry(t).or_default().push(ThmID(i as u32))
+      }
+    }
+    *n = self.env.thms.len();
+    match index.get(&t) {Some(v) => v, None => &[]}
+  }
+
+  /// Roll back the environment to `snap` for `with-env-snapshot`, and drop any cached
+  /// data and prepared statements (with handles `prepared` and above) that may refer to
+  /// the removed declarations.
+  fn rollback_env(&mut self, snap: EnvSnapshot, prepared: usize) {
+    self.env.rollback(snap);
+    let (sorts, terms, thms) = (self.sorts.len(), self.terms.len(), self.thms.len());
+    self.spans.retain(|k| match *k {
+      ObjectKind::Sort(s) => (s.0 as usize) < sorts,
+      ObjectKind::Term(t, _) => (t.0 as usize) < terms,
+      ObjectKind::Thm(t) => (t.0 as usize) < thms,
+      _ => true,
+    });
+    self.proof_cache.retain(|t, _| (t.0 as usize) < thms);
+    self.deprecated.retain(|t, _| (t.0 as usize) < thms);
+    if self.thm_heads.0 > thms { self.thm_heads = Default::default() }
+    self.prepared.1.retain(|&h, _| h < prepared);
+  }
+
+  fn subst_thm(&self, t: ThmID, args: Vec<LispVal>) -> SResult<LispVal> {
+    let tdata = &self.env.thms[t];
+    if args.len() != tdata.args.len() {
+      return Err(format!("expected {} arguments", tdata.args.len()))
+    }
+    Ok(Subst::new(&self.env, &tdata.heap, args).subst(&tdata.ret)?)
+  }
+
   fn head_err(&self, e: &LispKind) -> SResult<LispVal> {
     e.unwrapped(|e| match e {
       LispKind::List(es) if es.is_empty() => Err("evaluating 'hd ()'".into()),
@@ -437,15 +984,25 @@ impl Elaborator {
       if j >= es.len() { r(es[i..].into()) }
       else { LispVal::dotted_list(es[i..j].cloned_box(), exponential_backoff(es, j, r)) }
     }
-    e.unwrapped(|e| match e {
-      LispKind::List(es) if es.is_empty() => Err("evaluating 'tl ()'".into()),
-      LispKind::List(es) =>
-        Ok(exponential_backoff(es, 1, LispVal::list)),
-      LispKind::DottedList(es, r) if es.is_empty() => self.tail(r),
-      LispKind::DottedList(es, r) =>
-        Ok(exponential_backoff(es, 1, |v| LispVal::dotted_list(v, r.clone()))),
-      _ => Err(format!("expected a list, got {}", self.print(e)))
-    })
+    // Dotted lists with an empty prefix are skipped in a loop rather than by recursion,
+    // so that deeply nested dotted lists do not overflow the stack.
+    let mut e = e;
+    let mut cur;
+    loop {
+      let next = e.unwrapped(|e| -> SResult<std::result::Result<LispVal, LispVal>> {match e {
+        LispKind::List(es) if es.is_empty() => Err("evaluating 'tl ()'".into()),
+        LispKind::List(es) =>
+          Ok(Ok(exponential_backoff(es, 1, LispVal::list))),
+        LispKind::DottedList(es, r) if es.is_empty() => Ok(Err(r.clone())),
+        LispKind::DottedList(es, r) =>
+          Ok(Ok(exponential_backoff(es, 1, |v| LispVal::dotted_list(v, r.clone())))),
+        _ => Err(format!("expected a list, got {}", self.print(e)))
+      }})?;
+      match next {
+        Ok(res) => return Ok(res),
+        Err(r) => {cur = r; e = &*cur}
+      }
+    }
   }
 
   fn nth(&self, e: &LispKind, i: usize) -> SResult<LispVal> {
@@ -499,7 +1056,15 @@ impl Elaborator {
     }
   }
 
-  fn get_proof(&self, t: ThmID, mut heap: Vec<LispVal>) -> LispVal {
+  fn get_proof(&mut self, t: ThmID, heap: Vec<LispVal>) -> LispVal {
+    if let Some(e) = self.proof_cache.get(&t) {return e.clone()}
+    let e = self.build_proof(t, heap);
+    if self.proof_cache.len() >= PROOF_CACHE_SIZE {self.proof_cache.clear()}
+    self.proof_cache.insert(t, e.clone());
+    e
+  }
+
+  fn build_proof(&self, t: ThmID, mut heap: Vec<LispVal>) -> LispVal {
     let tdata = &self.thms[t];
     match &tdata.proof {
       Some(Some(pr)) => {
@@ -515,7 +1080,20 @@ impl Elaborator {
     }
   }
 
-  fn get_decl(&mut self, fsp: Option<FileSpan>, x: AtomID) -> LispVal {
+  /// Build the expression heap of theorem `t`, containing its variables followed by
+  /// the shared subterms of its statement.
+  fn thm_heap(&self, t: ThmID) -> Vec<LispVal> {
+    let tdata = &self.thms[t];
+    let (mut heap, mut bvs) = (Vec::new(), Vec::new());
+    self.binders(&tdata.args, &mut heap, &mut bvs);
+    for e in &tdata.heap[heap.len()..] {
+      let e = self.expr_node(&heap, &mut None, e);
+      heap.push(e)
+    }
+    heap
+  }
+
+  fn get_decl(&mut self, fsp: Option<FileSpan>, x: AtomID, eager: bool) -> LispVal {
     fn vis(mods: Modifiers) -> LispVal {
       match mods {
         Modifiers::PUB => LispVal::atom(AtomID::PUB),
@@ -581,12 +1159,253 @@ impl Elaborator {
         if tdata.proof.is_some() {
           args.push(vis(tdata.vis));
           heap.truncate(tdata.args.len());
-          args.push(LispVal::proc(Proc::ProofThunk(x, RefCell::new(Err(heap.into())))));
+          args.push(if eager {self.get_proof(t, heap)} else {
+            LispVal::proc(Proc::ProofThunk(x, RefCell::new(Err(heap.into()))))
+          });
+        }
+        LispVal::list(args)
+      }
+    }
+  }
+
+  fn get_sort(&mut self, fsp: Option<FileSpan>, x: AtomID) -> LispVal {
+    match self.data[x].sort {
+      None => LispVal::undef(),
+      Some(s) => {
+        if let Some(fsp) = fsp {
+          self.spans.insert_if(fsp.span, || ObjectKind::Sort(s));
+        }
+        let mods = self.sorts[s].mods;
+        let mods = [
+          (Modifiers::PURE, AtomID::PURE),
+          (Modifiers::STRICT, AtomID::STRICT),
+          (Modifiers::PROVABLE, AtomID::PROVABLE),
+          (Modifiers::FREE, AtomID::FREE),
+        ].iter().filter(|p| mods.contains(p.0)).map(|p| LispVal::atom(p.1)).collect::<Vec<_>>();
+        LispVal::list(vec![LispVal::atom(AtomID::SORT), LispVal::atom(x), LispVal::list(mods)])
+      }
+    }
+  }
+
+  /// The parts of the proof state that are compared by `made-progress?`: the goals
+  /// and their types, and the number of hypotheses.
+  fn progress_snapshot(&self) -> (Vec<(LispVal, Option<LispVal>)>, (usize, usize)) {
+    (self.lc.goals.iter().map(|g| (g.clone(), g.goal_type())).collect(),
+     (self.lc.proofs.len(), self.lc.proof_order.len()))
+  }
+
+  /// Save the current proof state, including the current values of the
+  /// goals and metavariables, so that it can be restored by `restore_lc`.
+  fn snapshot_lc(&self) -> (Box<LocalContext>, Vec<(LispVal, LispVal)>) {
+    let refs = self.lc.mvars.iter().chain(&self.lc.goals)
+      .filter_map(|e| Some((e.clone(), e.as_ref_(|v| v.clone())?))).collect();
+    (Box::new(self.lc.clone()), refs)
+  }
+
+  /// Restore the proof state saved by `snapshot_lc`.
+  fn restore_lc(&mut self, lc: Box<LocalContext>, refs: Vec<(LispVal, LispVal)>) {
+    for (e, v) in refs { e.as_ref_(|r| *r = v); }
+    self.lc = *lc;
+  }
+
+  /// Get the LHS (if `right = false`) or RHS (if `right = true`) of the conversion
+  /// proof `c`, following the same rules as [`ProofHash::conv_side`].
+  ///
+  /// [`ProofHash::conv_side`]: ../../proof/enum.ProofHash.html#method.conv_side
+  fn conv_side(&self, c: &LispVal, right: bool) -> SResult<LispVal> {
+    if !c.is_list() {return Ok(c.clone())}
+    let mut u = Uncons::from(c.clone());
+    let head = u.next().ok_or("expected a conversion, got ()")?;
+    Ok(match head.as_atom() {
+      Some(AtomID::SYM) => match (u.next(), u.exactly(0)) {
+        (Some(c), true) => self.conv_side(&c, !right)?,
+        _ => return Err(format!("incorrect :sym format {}", self.print(c)))
+      },
+      Some(AtomID::REFL) => match (u.next(), u.exactly(0)) {
+        (Some(e), true) => e,
+        _ => return Err(format!("incorrect :refl format {}", self.print(c)))
+      },
+      Some(AtomID::CONG) => {
+        let mut args = vec![u.next().ok_or_else(|| format!("incorrect :cong format {}", self.print(c)))?];
+        for c in u {args.push(self.conv_side(&c, right)?)}
+        LispVal::list(args)
+      }
+      Some(AtomID::UNFOLD) => {
+        let (t, es, prf) = match (u.next(), u.next(), u.next(), u.next()) {
+          (Some(t), Some(es), Some(prf), None) |
+          (Some(t), Some(es), Some(_), Some(prf)) if u.exactly(0) => (t, es, prf),
+          _ => return Err(format!("incorrect :unfold format {}", self.print(c)))
+        };
+        if right {self.conv_side(&prf, true)?} else {
+          let mut args = vec![t];
+          args.extend(Uncons::from(es));
+          LispVal::list(args)
         }
+      }
+      Some(a) if self.term(a).is_some() => {
+        let mut args = vec![head];
+        for c in u {args.push(self.conv_side(&c, right)?)}
         LispVal::list(args)
       }
+      _ => return Err(format!("expected a conversion, got {}", self.print(c)))
+    })
+  }
+
+  /// Collect the bound variables in the local context that the expression `e` depends on.
+  fn bound_vars(&self, e: &LispVal, out: &mut HashSet<AtomID>) {
+    if let Some(a) = e.as_atom() {
+      match self.lc.vars.get(&a) {
+        Some((_, InferSort::Bound(_))) => {out.insert(a);}
+        Some((_, InferSort::Reg(_, deps))) => out.extend(deps.iter().copied()),
+        _ => {}
+      }
+    } else if e.is_list() {
+      for e in Uncons::from(e.clone()).skip(1) {self.bound_vars(&e, out)}
     }
   }
+
+  /// Find the first unassigned goal at index `i` or later, returning its index and type.
+  fn next_goal_from(&self, i: usize) -> Option<(usize, LispVal)> {
+    self.lc.goals.iter().enumerate().skip(i).find_map(|(j, g)| Some((j, g.goal_type()?)))
+  }
+
+  fn report_mode(&self, e: &LispVal) -> SResult<ReportMode> {
+    if let Some(b) = e.as_bool() {
+      return Ok(ReportMode {error: b, warn: b, info: b})
+    }
+    if !e.is_list() {return Err("expected a boolean or a list of error types".into())}
+    let mut mode = ReportMode {error: false, warn: false, info: false};
+    for e in Uncons::from(e.clone()) {
+      match e.as_atom().ok_or("expected an atom")? {
+        AtomID::ERROR => mode.error = true,
+        AtomID::WARN => mode.warn = true,
+        AtomID::INFO => mode.info = true,
+        s => return Err(format!("unknown error level '{}'", self.print(&s)))
+      }
+    }
+    Ok(mode)
+  }
+
+  /// Get the next value from the pseudo-random number generator (splitmix64).
+  fn next_random(&mut self) -> u64 {
+    self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.rng;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  /// Get a pseudo-random number in the range `0..n`, where `n` is positive.
+  /// We generate 64 more bits than needed so that the bias from the modulus is negligible.
+  fn random_below(&mut self, n: &BigInt) -> BigInt {
+    let mut r = BigInt::zero();
+    for _ in 0..n.bits() / 64 + 2 {
+      r = (r << 64) + BigInt::from(self.next_random())
+    }
+    r % n
+  }
+
+  /// Add a `prefix` (if `infix` is `None`) or `infixl/r` (if `infix` is `Some(right)`)
+  /// notation for term `t`, as in the corresponding statements.
+  fn add_simple_nota(&mut self, fsp: FileSpan, tk: ArcString, t: TermID, prec: Prec, infix: Option<bool>) -> Result<()> {
+    let sp = fsp.span;
+    let (rassoc, nargs, lits) = self.simple_nota_lits(sp, t, &tk, prec, infix)?;
+    self.add_const_at(sp, tk.clone(), fsp.clone(), prec)?;
+    let info = NotaInfo { span: fsp, term: t, nargs, rassoc: Some(rassoc), lits };
+    match infix {
+      None => self.pe.add_prefix(tk.clone(), info),
+      Some(_) => self.pe.add_infix(tk.clone(), info),
+    }.map_err(|r| ElabError::with_info(sp,
+      format!("constant '{}' already declared", tk).into(),
+      vec![(r.decl1, "declared here".into())]))
+  }
+
+  /// Add a general `notation` for term `t` at precedence `prec`, where `lits` is a list of
+  /// literals in the format of `add-notation!`.
+  fn add_notation(&mut self, fsp: FileSpan, t: TermID, prec: Prec,
+      lits: &LispVal, assoc: Option<bool>) -> Result<()> {
+    let sp = fsp.span;
+    if !lits.is_list() {return Err(ElabError::new_e(sp, "expected a list of literals"))}
+    let nargs = self.terms[t].args.len();
+    let mut used = vec![false; nargs];
+    let (mut consts, mut out) = (vec![], vec![]);
+    for lit in Uncons::from(lits.clone()) {
+      let (c, q) = if let Some(c) = lit.unwrapped(|e|
+        if let LispKind::String(s) = e {Some(s.clone())} else {None}) {
+        let q = if consts.is_empty() {prec} else {
+          self.pe.consts.get(&c).map(|c| c.1).ok_or_else(|| ElabError::new_e(sp,
+            format!("constant '{}' needs a precedence", c)))?
+        };
+        (c, q)
+      } else {
+        let mut u = Uncons::from(lit.clone());
+        let (x, q) = match (u.next(), u.next(), u.exactly(0)) {
+          (Some(x), Some(q), true) => (x, self.as_prec(&q).map_err(|e| ElabError::new_e(sp, e))?),
+          _ => return Err(ElabError::new_e(sp,
+            format!("expected a literal, got {}", self.print(&lit))))
+        };
+        if let Some(i) = x.as_int(|n| n.to_usize()) {
+          let i = i.filter(|&i| i < nargs).ok_or_else(|| ElabError::new_e(sp,
+            format!("variable index out of range for {}-ary term", nargs)))?;
+          used[i] = true;
+          out.push(Literal::Var(i, q));
+          continue
+        }
+        let c = self.as_string(&x).map_err(|e| ElabError::new_e(sp, e))?;
+        (c, q)
+      };
+      consts.push((c.clone(), q));
+      out.push(Literal::Const(c))
+    }
+    let infix = match (out.get(0), out.get(1)) {
+      (None, _) => return Err(ElabError::new_e(sp, "notation requires at least one literal")),
+      (Some(Literal::Const(_)), _) => false,
+      (Some(Literal::Var(..)), Some(Literal::Const(_))) => true,
+      (Some(Literal::Var(..)), Some(Literal::Var(..))) => return Err(ElabError::new_e(sp,
+        "notation cannot start with two variables")),
+      (Some(Literal::Var(..)), None) => return Err(ElabError::new_e(sp,
+        "notation requires at least one constant")),
+    };
+    if let Some(i) = used.iter().position(|&b| !b) {
+      return Err(ElabError::new_e(sp, format!("variable {} not used in notation", i)))
+    }
+    let rassoc = if infix {
+      let r = assoc.ok_or_else(|| ElabError::new_e(sp,
+        "general infix notation requires explicit associativity"))?;
+      let n = if let Prec::Prec(n) = prec {n} else {
+        return Err(ElabError::new_e(sp, "infix constants cannot have prec max"))
+      };
+      self.pe.add_prec_assoc(n, fsp.clone(), r).map_err(|e| ElabError::with_info(sp,
+        format!("precedence level {} has incompatible associativity", n).into(),
+        vec![(e.decl1, format!("{} assoc here", if r {"left"} else {"right"}).into())]))?;
+      r
+    } else {true};
+    let tk = consts[0].0.clone();
+    for (c, q) in consts { self.add_const_at(sp, c, fsp.clone(), q)? }
+    let info = NotaInfo { span: fsp, term: t, nargs, rassoc: Some(rassoc), lits: out };
+    if infix {self.pe.add_infix(tk.clone(), info)} else {self.pe.add_prefix(tk.clone(), info)}
+      .map_err(|r| ElabError::with_info(sp,
+        format!("constant '{}' already declared", tk).into(),
+        vec![(r.decl1, "declared here".into())]))
+  }
+
+  fn get_notation(&self, t: TermID) -> LispVal {
+    let (coe, nots) = match self.pe.decl_nota.get(&t) {
+      None => return LispVal::undef(),
+      Some(&(coe, ref nots)) => (coe, nots)
+    };
+    let nots = nots.iter().map(|(c, infx)| {
+      let prec = match self.pe.consts.get(c) {
+        Some(&(_, Prec::Prec(n))) => LispVal::number(n.into()),
+        Some(&(_, Prec::Max)) => LispVal::atom(AtomID::MAX),
+        None => LispVal::undef(),
+      };
+      let rassoc = (if *infx {&self.pe.infixes} else {&self.pe.prefixes}).get(c)
+        .and_then(|n| n.rassoc).map_or_else(LispVal::undef, LispVal::bool);
+      LispVal::list(vec![LispVal::string(c.clone()), LispVal::bool(*infx), prec, rassoc])
+    }).collect::<Vec<_>>();
+    LispVal::list(vec![LispVal::bool(coe), LispVal::list(nots)])
+  }
 }
 
 struct Evaluator<'a> {
@@ -595,6 +1414,7 @@ struct Evaluator<'a> {
   file: FileRef,
   orig_span: Span,
   stack: Vec<Stack<'a>>,
+  budget: Option<usize>,
 }
 impl<'a> Deref for Evaluator<'a> {
   type Target = Elaborator;
@@ -607,7 +1427,7 @@ impl<'a> DerefMut for Evaluator<'a> {
 impl<'a> Evaluator<'a> {
   fn new(elab: &'a mut Elaborator, orig_span: Span) -> Evaluator<'a> {
     let file = elab.path.clone();
-    Evaluator {elab, ctx: vec![], file, orig_span, stack: vec![]}
+    Evaluator {elab, ctx: vec![], file, orig_span, stack: vec![], budget: None}
   }
 
   fn fspan_base(&mut self, sp: Span) -> FileSpan {
@@ -664,6 +1484,27 @@ impl<'a> Evaluator<'a> {
     self.make_stack_err(sp, ErrorLevel::Error, "error occurred here".into(), err)
   }
 
+  /// Construct the list `start, start + step, ...` stopping before `end`.
+  /// Because the list is allocated eagerly, we check for timeout and cancellation
+  /// periodically so that a huge range doesn't hang the elaborator.
+  fn range(&self, mut i: BigInt, end: &BigInt, step: &BigInt) -> SResult<LispVal> {
+    let up = step.is_positive();
+    let mut out = vec![];
+    while if up {i < *end} else {i > *end} {
+      if out.len() % 0x10000 == 0xffff {
+        if self.cur_timeout.map_or(false, |t| t < Instant::now()) {
+          return Err("timeout".into())
+        }
+        if self.cancel.load(Ordering::Relaxed) {
+          return Err("cancelled".into())
+        }
+      }
+      out.push(LispVal::number(i.clone()));
+      i += step;
+    }
+    Ok(LispVal::list(out))
+  }
+
   fn add_thm(&mut self, fsp: FileSpan, args: &[LispVal]) -> Result<State<'a>> {
     Ok(match self.elab.add_thm(fsp.clone(), args)? {
       Ok(()) => State::Ret(LispVal::undef()),
@@ -689,7 +1530,26 @@ macro_rules! make_builtins {
     }
 
     impl<'a> Evaluator<'a> {
-      fn evaluate_builtin(&mut $self, $sp1: Span, $sp2: Span, f: BuiltinProc, mut $args: Vec<LispVal>) -> Result<State<'a>> {
+      /// Evaluate a builtin, recording the call if it is a tactic and recording is on.
+      /// A tactic that continues in a later state (like `refine`) is only recorded
+      /// once that state returns, so tactics that fail are never recorded.
+      fn evaluate_builtin(&mut $self, $sp1: Span, $sp2: Span, f: BuiltinProc, $args: Vec<LispVal>) -> Result<State<'a>> {
+        let call = if $self.recording && f.is_tactic() {
+          let mut call = vec![LispVal::atom($self.get_atom(f.to_str()))];
+          call.extend_from_slice(&$args);
+          Some(LispVal::list(call))
+        } else {None};
+        let st = $self.evaluate_builtin_inner($sp1, $sp2, f, $args)?;
+        if let Some(call) = call {
+          match st {
+            State::Ret(_) => $self.recorded.push(call),
+            _ => $self.stack.push(Stack::Record(call)),
+          }
+        }
+        Ok(st)
+      }
+
+      fn evaluate_builtin_inner(&mut $self, $sp1: Span, $sp2: Span, f: BuiltinProc, mut $args: Vec<LispVal>) -> Result<State<'a>> {
         macro_rules! print {($sp:expr, $x:expr) => {{
           let msg = $x; $self.info($sp, false, f.to_str(), msg)
         }}}
@@ -711,25 +1571,61 @@ make_builtins! { self, sp1, sp2, args,
   Display: Exact(1) => {print!(sp1, &*try1!(self.as_string(&args[0]))); LispVal::undef()},
   Error: Exact(1) => try1!(Err(&*try1!(self.as_string(&args[0])))),
   Print: Exact(1) => {print!(sp1, format!("{}", self.print(&args[0]))); LispVal::undef()},
-  ReportAt: Exact(3) => {
+  ReportAt: AtLeast(3) => {
     let level = match args[0].as_atom() {
       Some(AtomID::ERROR) => ErrorLevel::Error,
       Some(AtomID::WARN) => ErrorLevel::Warning,
       Some(AtomID::INFO) =>  ErrorLevel::Info,
       _ => try1!(Err("expected 'error, 'warn, or 'info"))
     };
+    let related = match args.get(3) {
+      None => None,
+      Some(_) if args.len() > 4 => try1!(Err("expected 3 or 4 arguments")),
+      Some(rel) => {
+        let mut info: Vec<(FileSpan, BoxError)> = vec![];
+        for e in Uncons::from(rel.clone()) {
+          let mut u = Uncons::from(e.clone());
+          match (u.next().and_then(|sp| sp.fspan()), u.next(), u.exactly(0)) {
+            (Some(fsp), Some(msg), true) =>
+              info.push((fsp, (*try1!(self.as_string(&msg))).into())),
+            _ => try1!(Err(format!("expected a (span message) pair, got {}", self.print(&e))))
+          }
+        }
+        Some(info)
+      }
+    };
     let FileSpan {file, span} = try1!(args[1].fspan().ok_or("expected a span"));
     if file == self.file {
       let s = (*try1!(self.as_string(&args[2]))).into();
       let msg = if let Some(true) = args[1].as_bool() {
-        self.make_stack_err(Some((span, true)), level, "(report-at)".into(), s)
+        let mut err = self.make_stack_err(Some((span, true)), level, "(report-at)".into(), s);
+        if let (ElabErrorKind::Boxed(_, info), Some(related)) = (&mut err.kind, related) {
+          info.get_or_insert_with(Vec::new).extend(related)
+        }
+        err
       } else {
-        ElabError { pos: span, level, kind: ElabErrorKind::Boxed(s, None) }
+        ElabError { pos: span, level, kind: ElabErrorKind::Boxed(s, related) }
       };
       self.report(msg);
     }
     LispVal::undef()
   },
+  AnnotateSpan: Exact(2) => {
+    let FileSpan {file, span} = try1!(args[0].fspan().ok_or("expected a span"));
+    let s = try1!(self.as_string(&args[1]));
+    if file == self.file {
+      self.spans.insert_if(span, || ObjectKind::Custom(s));
+    }
+    LispVal::undef()
+  },
+  AnnotateGoto: Exact(2) => {
+    let FileSpan {file, span} = try1!(args[0].fspan().ok_or("expected a span"));
+    let target = try1!(args[1].fspan().ok_or("expected a span"));
+    if file == self.file {
+      self.spans.insert_if(span, || ObjectKind::Goto(target));
+    }
+    LispVal::undef()
+  },
   Begin: AtLeast(0) => args.last().cloned().unwrap_or_else(LispVal::undef),
   Apply: AtLeast(2) => {
     let proc = args.remove(0);
@@ -747,29 +1643,63 @@ make_builtins! { self, sp1, sp2, args,
     }
     return Ok(State::App(sp1, sp, proc, args, [].iter()))
   },
-  Add: AtLeast(0) => {
+  Add: AtLeast(0) => if args.iter().any(|e| e.is_ratio()) {
+    let mut q = BigRational::zero();
+    for e in args { q += try1!(self.as_ratio(&e)) }
+    LispVal::ratio(q)
+  } else {
     let mut n: BigInt = 0.into();
     for e in args { n += try1!(self.as_int(&e)) }
     LispVal::number(n)
   },
-  Mul: AtLeast(0) => {
+  Mul: AtLeast(0) => if args.iter().any(|e| e.is_ratio()) {
+    let mut q = BigRational::one();
+    for e in args { q *= try1!(self.as_ratio(&e)) }
+    LispVal::ratio(q)
+  } else {
     let mut n: BigInt = 1.into();
     for e in args { n *= try1!(self.as_int(&e)) }
     LispVal::number(n)
   },
-  Max: AtLeast(1) => {
+  Max: AtLeast(1) => if args.iter().any(|e| e.is_ratio()) {
+    let mut it = args.into_iter();
+    let mut q = try1!(self.as_ratio(&it.next().unwrap()));
+    for e in it { q = q.max(try1!(self.as_ratio(&e))) }
+    LispVal::ratio(q)
+  } else {
     let mut it = args.into_iter();
     let mut n: BigInt = try1!(self.as_int(&it.next().unwrap()));
     for e in it { n = n.max(try1!(self.as_int(&e)).clone()) }
     LispVal::number(n)
   },
-  Min: AtLeast(1) => {
+  Min: AtLeast(1) => if args.iter().any(|e| e.is_ratio()) {
+    let mut it = args.into_iter();
+    let mut q = try1!(self.as_ratio(&it.next().unwrap()));
+    for e in it { q = q.min(try1!(self.as_ratio(&e))) }
+    LispVal::ratio(q)
+  } else {
     let mut it = args.into_iter();
     let mut n: BigInt = try1!(self.as_int(&it.next().unwrap()));
     for e in it { n = n.min(try1!(self.as_int(&e)).clone()) }
     LispVal::number(n)
   },
-  Sub: AtLeast(1) => if args.len() == 1 {
+  Abs: Exact(1) => if args[0].is_ratio() {
+    LispVal::ratio(try1!(self.as_ratio(&args[0])).abs())
+  } else {
+    LispVal::number(try1!(self.as_int(&args[0])).abs())
+  },
+  Sign: Exact(1) => if args[0].is_ratio() {
+    LispVal::ratio(try1!(self.as_ratio(&args[0])).signum())
+  } else {
+    LispVal::number(try1!(self.as_int(&args[0])).signum())
+  },
+  Sub: AtLeast(1) => if args.iter().any(|e| e.is_ratio()) {
+    let mut it = args.into_iter();
+    let mut q = try1!(self.as_ratio(&it.next().unwrap()));
+    if it.len() == 0 { q = -q }
+    for e in it { q -= try1!(self.as_ratio(&e)) }
+    LispVal::ratio(q)
+  } else if args.len() == 1 {
     LispVal::number(-try1!(self.as_int(&args[0])))
   } else {
     let mut it = args.into_iter();
@@ -777,23 +1707,63 @@ make_builtins! { self, sp1, sp2, args,
     for e in it { n -= try1!(self.as_int(&e)) }
     LispVal::number(n)
   },
-  Div: AtLeast(1) => {
+  Div: AtLeast(1) => if args.iter().any(|e| e.is_ratio()) {
+    let mut it = args.into_iter();
+    let mut q = try1!(self.as_ratio(&it.next().unwrap()));
+    for e in it {
+      let d = try1!(self.as_ratio(&e));
+      if d.is_zero() {try1!(Err("division by zero"))}
+      q /= d
+    }
+    LispVal::ratio(q)
+  } else {
     let mut it = args.into_iter();
     let mut n: BigInt = try1!(self.as_int(&it.next().unwrap()));
     for e in it { n /= try1!(self.as_int(&e)) }
     LispVal::number(n)
   },
-  Mod: AtLeast(1) => {
+  Mod: AtLeast(1) => if args.iter().any(|e| e.is_ratio()) {
+    let mut it = args.into_iter();
+    let mut q = try1!(self.as_ratio(&it.next().unwrap()));
+    for e in it {
+      let d = try1!(self.as_ratio(&e));
+      if d.is_zero() {try1!(Err("division by zero"))}
+      q %= d
+    }
+    LispVal::ratio(q)
+  } else {
     let mut it = args.into_iter();
     let mut n: BigInt = try1!(self.as_int(&it.next().unwrap()));
     for e in it { n %= try1!(self.as_int(&e)) }
     LispVal::number(n)
   },
-  Lt: AtLeast(1) => LispVal::bool(try1!(self.int_bool_binop(|a, b| a < b, &args))),
-  Le: AtLeast(1) => LispVal::bool(try1!(self.int_bool_binop(|a, b| a <= b, &args))),
-  Gt: AtLeast(1) => LispVal::bool(try1!(self.int_bool_binop(|a, b| a > b, &args))),
-  Ge: AtLeast(1) => LispVal::bool(try1!(self.int_bool_binop(|a, b| a >= b, &args))),
-  Eq: AtLeast(1) => LispVal::bool(try1!(self.int_bool_binop(|a, b| a == b, &args))),
+  Ratio: Exact(2) => {
+    let d = try1!(self.as_int(&args[1]));
+    if d.is_zero() {try1!(Err("division by zero"))}
+    LispVal::ratio(BigRational::new(try1!(self.as_int(&args[0])), d))
+  },
+  SetSeed: Exact(1) => {
+    self.rng = try1!(args[0].as_int(|n| n.to_u64()).flatten()
+      .ok_or("expected a nonnegative 64-bit number"));
+    LispVal::undef()
+  },
+  Random: Exact(1) => {
+    let n = try1!(self.as_int(&args[0]));
+    if !n.is_positive() {try1!(Err("expected a positive number"))}
+    LispVal::number(self.random_below(&n))
+  },
+  Choose: Exact(1) => {
+    if !args[0].is_list() {try1!(Err("expected a list"))}
+    let mut es: Vec<_> = Uncons::from(args.pop().unwrap()).collect();
+    if es.is_empty() {try1!(Err("choose: empty list"))}
+    let i = self.random_below(&BigInt::from(es.len())).to_usize().unwrap();
+    es.swap_remove(i)
+  },
+  Lt: AtLeast(1) => LispVal::bool(try1!(self.num_bool_binop(|o| o == cmp::Ordering::Less, &args))),
+  Le: AtLeast(1) => LispVal::bool(try1!(self.num_bool_binop(|o| o != cmp::Ordering::Greater, &args))),
+  Gt: AtLeast(1) => LispVal::bool(try1!(self.num_bool_binop(|o| o == cmp::Ordering::Greater, &args))),
+  Ge: AtLeast(1) => LispVal::bool(try1!(self.num_bool_binop(|o| o != cmp::Ordering::Less, &args))),
+  Eq: AtLeast(1) => LispVal::bool(try1!(self.num_bool_binop(|o| o == cmp::Ordering::Equal, &args))),
   Equal: AtLeast(1) => {
     let (e1, args) = args.split_first().unwrap();
     LispVal::bool(args.iter().all(|e2| e1 == e2))
@@ -808,6 +1778,38 @@ make_builtins! { self, sp1, sp2, args,
     for e in args { out.push_str(&try1!(self.as_string(&e))) }
     LispVal::string(ArcString::new(out))
   },
+  Format: AtLeast(1) => {
+    let fmt = try1!(self.as_string(&args[0]));
+    LispVal::string(ArcString::new(try1!(self.format(&fmt, &args[1..]))))
+  },
+  StringContains: Exact(2) =>
+    LispVal::bool(try1!(self.as_string(&args[0])).contains(&*try1!(self.as_string(&args[1])))),
+  StringStartsWith: Exact(2) =>
+    LispVal::bool(try1!(self.as_string(&args[0])).starts_with(&*try1!(self.as_string(&args[1])))),
+  StringEndsWith: Exact(2) =>
+    LispVal::bool(try1!(self.as_string(&args[0])).ends_with(&*try1!(self.as_string(&args[1])))),
+  StringIndexOf: Exact(2) => {
+    let (t, s) = (try1!(self.as_string(&args[0])), try1!(self.as_string(&args[1])));
+    match s.find(&*t) {
+      None => LispVal::undef(),
+      Some(i) => LispVal::number(s.char_indices().take_while(|&(j, _)| j < i).count().into())
+    }
+  },
+  StringUpcase: Exact(1) => LispVal::string(ArcString::new(try1!(self.as_string(&args[0])).to_uppercase())),
+  StringDowncase: Exact(1) => LispVal::string(ArcString::new(try1!(self.as_string(&args[0])).to_lowercase())),
+  CharToNumber: Exact(1) => {
+    let s = try1!(self.as_string(&args[0]));
+    let mut it = s.chars();
+    match (it.next(), it.next()) {
+      (Some(c), None) => LispVal::number(u32::from(c).into()),
+      _ => try1!(Err("expected a string with one character"))
+    }
+  },
+  NumberToChar: Exact(1) => {
+    let c = try1!(args[0].as_int(|n| n.to_u32().and_then(std::char::from_u32)).flatten()
+      .ok_or("expected a valid unicode scalar value"));
+    LispVal::string(ArcString::new(c.to_string()))
+  },
   Not: AtLeast(0) => LispVal::bool(!args.iter().any(|e| e.truthy())),
   And: AtLeast(0) => LispVal::bool(args.iter().all(|e| e.truthy())),
   Or: AtLeast(0) => LispVal::bool(args.iter().any(|e| e.truthy())),
@@ -835,19 +1837,44 @@ make_builtins! { self, sp1, sp2, args,
     return Ok(State::MapProc(sp1, sp, proc,
       it.map(Uncons::from).collect(), vec![]))
   },
+  Range: AtLeast(1) => {
+    let (start, end, step) = match &*args {
+      [n] => (BigInt::zero(), try1!(self.as_int(n)), 1.into()),
+      [a, b] => (try1!(self.as_int(a)), try1!(self.as_int(b)), 1.into()),
+      [a, b, s] => (try1!(self.as_int(a)), try1!(self.as_int(b)), try1!(self.as_int(s))),
+      _ => try1!(Err("expected 1 to 3 arguments")),
+    };
+    if step.is_zero() {try1!(Err("range: step must be nonzero"))}
+    try1!(self.range(start, &end, &step))
+  },
   IsBool: Exact(1) => LispVal::bool(args[0].is_bool()),
   IsAtom: Exact(1) => LispVal::bool(args[0].is_atom()),
   IsPair: Exact(1) => LispVal::bool(args[0].at_least(1)),
   IsNull: Exact(1) => LispVal::bool(args[0].exactly(0)),
-  IsNumber: Exact(1) => LispVal::bool(args[0].is_int()),
+  IsNumber: Exact(1) => LispVal::bool(args[0].is_int() || args[0].is_ratio()),
+  IsRatio: Exact(1) => LispVal::bool(args[0].is_ratio()),
   IsString: Exact(1) => LispVal::bool(args[0].is_string()),
   IsProc: Exact(1) => LispVal::bool(args[0].is_proc()),
   IsDef: Exact(1) => LispVal::bool(args[0].is_def()),
   IsRef: Exact(1) => LispVal::bool(args[0].is_ref()),
+  WeakRef: Exact(1) => try1!(args[0].downgrade().ok_or("expected a ref-cell")),
+  Upgrade: Exact(1) => try1!(args[0].unwrapped(|e|
+    if let LispKind::WeakRef(w) = e {Some(w.upgrade().unwrap_or_else(LispVal::undef))} else {None})
+    .ok_or("expected a weak reference")),
+  RefEq: Exact(2) => {
+    let r1 = try1!(args[0].as_lref().ok_or_else(|| format!("not a ref-cell: {}", self.print(&args[0]))));
+    let r2 = try1!(args[1].as_lref().ok_or_else(|| format!("not a ref-cell: {}", self.print(&args[1]))));
+    LispVal::bool(std::ptr::eq(r1, r2))
+  },
   NewRef: AtLeast(0) => LispVal::new_ref(args.get(0).cloned().unwrap_or_else(LispVal::undef)),
   GetRef: Exact(1) => try1!(self.as_ref(&args[0], |e| Ok(e.clone()))),
   SetRef: Exact(2) => {
-    try1!(self.as_ref(&args[0], |e| {*e = args[1].clone(); Ok(())}));
+    let mvar = try1!(self.as_ref(&args[0], |e| {
+      let mvar = e.is_mvar();
+      *e = args[1].clone();
+      Ok(mvar)
+    }));
+    if mvar {self.lc.mvars_assigned += 1}
     LispVal::undef()
   },
   CopySpan: Exact(2) => {
@@ -857,6 +1884,10 @@ make_builtins! { self, sp1, sp2, args,
       (None, e) => e
     }
   },
+  CopySpanDeep: Exact(2) => match args[0].fspan() {
+    Some(sp) => args[1].replace_span_deep(&sp),
+    None => args.pop().unwrap()
+  },
   StackSpan: Exact(1) => {
     let n = try1!(args[0].as_int(|n| n.to_usize().unwrap_or(usize::MAX)).ok_or("expected a number"));
     match self.stack_span(n) {
@@ -864,6 +1895,36 @@ make_builtins! { self, sp1, sp2, args,
       None => LispVal::undef()
     }
   },
+  SpanStack: Exact(0) => LispVal::list(self.stack.iter().rev().filter_map(|s| match s {
+    Stack::Ret(fsp, _, _, _) => Some(LispVal::undef().span(fsp.clone())),
+    _ => None
+  }).collect::<Vec<_>>()),
+  SpanText: Exact(1) => match args[0].fspan() {
+    Some(FileSpan {file, span}) if file == self.path => {
+      match self.ast.source.get(span.start..span.end) {
+        Some(s) => LispVal::string(ArcString::new(s.into())),
+        None => LispVal::undef(),
+      }
+    }
+    _ => LispVal::undef(),
+  },
+  SpanStart: Exact(1) => args[0].fspan()
+    .map_or_else(LispVal::undef, |fsp| LispVal::number(fsp.span.start.into())),
+  SpanEnd: Exact(1) => args[0].fspan()
+    .map_or_else(LispVal::undef, |fsp| LispVal::number(fsp.span.end.into())),
+  SpanFile: Exact(1) => args[0].fspan()
+    .map_or_else(LispVal::undef, |fsp| LispVal::string(fsp.file.rel().into())),
+  MakeSpan: Exact(3) => {
+    let rel = try1!(self.as_string(&args[0]));
+    let file = try1!(self.find_file(&rel).ok_or_else(|| format!("unknown file '{}'", rel)));
+    let start = try1!(args[1].as_int(|n| n.to_usize()).flatten().ok_or("expected a number"));
+    let end = try1!(args[2].as_int(|n| n.to_usize()).flatten().ok_or("expected a number"));
+    if start > end { try1!(Err("make-span: start is after end")) }
+    if file == self.path && end > self.ast.source.len() {
+      try1!(Err("make-span: span is out of range"))
+    }
+    LispVal::undef().span(FileSpan {file, span: (start..end).into()})
+  },
   Async: AtLeast(1) => {
     let proc = args.remove(0);
     let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
@@ -907,6 +1968,14 @@ make_builtins! { self, sp1, sp2, args,
     }).unwrap_or(None).ok_or("expected a mutable map")));
     LispVal::undef()
   },
+  MapRemove: Exact(2) => {
+    try1!(try1!(args[0].as_ref_mut(|r| {
+      r.as_map_mut(|m| -> SResult<_> {
+        let k = self.as_string_atom(&args[1])?;
+        Ok(m.remove(&k).unwrap_or_else(LispVal::undef))
+      })
+    }).unwrap_or(None).ok_or("expected a mutable map")))
+  },
   InsertNew: AtLeast(2) => {
     let mut it = args.into_iter();
     let mut m = it.next().unwrap();
@@ -920,6 +1989,20 @@ make_builtins! { self, sp1, sp2, args,
     }).ok_or("expected a map")));
     LispVal::undef()
   },
+  MergeMaps: AtLeast(0) => {
+    let mut m = HashMap::new();
+    for e in &args {
+      try1!(self.as_map(e, |m2| {
+        m.extend(m2.iter().map(|(&k, v)| (k, v.clone())));
+        Ok(())
+      }))
+    }
+    LispVal::new_ref(LispVal::new(LispKind::AtomMap(m)))
+  },
+  MapCopy: Exact(1) => {
+    let m = try1!(self.as_map(&args[0], |m| Ok(m.clone())));
+    LispVal::new_ref(LispVal::new(LispKind::AtomMap(m)))
+  },
   SetTimeout: Exact(1) => {
     match try1!(args[0].as_int(|n| n.to_u64()).ok_or("expected a number")) {
       None | Some(0) => {self.timeout = None; self.cur_timeout = None},
@@ -947,14 +2030,101 @@ make_builtins! { self, sp1, sp2, args,
       } else {try1!(Err("invalid arguments"))},
       Some(fsp))
   },
+  AssignMVar: Exact(2) => {
+    self.assign_mvar(sp1, &args[0], &args[1])?;
+    LispVal::undef()
+  },
   PrettyPrint: Exact(1) =>
     LispVal::string(ArcString::new(format!("{}", self.format_env().pp(&args[0], 80)))),
+  PrettyPrintWidth: Exact(2) => {
+    let width = try1!(args[1].as_int(|n| n.to_usize().unwrap_or(usize::MAX)).ok_or("expected a number"));
+    if width == 0 {try1!(Err("expected a positive width"))}
+    LispVal::string(ArcString::new(format!("{}", self.format_env().pp(&args[0], width.min(10000)))))
+  },
   NewGoal: Exact(1) => LispVal::goal(self.fspan(sp1), args.pop().unwrap()),
   GoalType: Exact(1) => try1!(args[0].goal_type().ok_or("expected a goal")),
   InferType: Exact(1) => self.infer_type(sp1, &args[0])?,
-  GetMVars: AtLeast(0) => LispVal::list(self.lc.mvars.clone()),
+  InferSort: Exact(1) => match args[0].mvar_target() {
+    Some(tgt) => tgt.sort().map_or_else(LispVal::undef, LispVal::atom),
+    None => LispVal::atom(self.sorts[self.infer_sort(sp1, &args[0])?].atom),
+  },
+  GetMVars: AtLeast(0) => {
+    self.lc.clean_mvars();
+    LispVal::list(self.lc.mvars.clone())
+  },
   GetGoals: AtLeast(0) => LispVal::list(self.lc.goals.clone()),
   SetGoals: AtLeast(0) => {self.lc.set_goals(args); LispVal::undef()},
+  GoalsClosedBy: Exact(1) => {
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let gs = self.lc.goals.clone();
+    self.stack.push(Stack::GoalsClosed(gs));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  RotateGoals: AtLeast(0) => {
+    if args.len() > 1 {try1!(Err("expected at most 1 argument"))}
+    let len = self.lc.goals.len();
+    if len != 0 {
+      let n = match args.first() {
+        None => 1,
+        Some(e) => {
+          let len = BigInt::from(len);
+          let n = try1!(self.as_int(e)) % &len;
+          (if n.is_negative() {n + len} else {n}).to_usize().unwrap()
+        }
+      };
+      self.lc.goals.rotate_left(n)
+    }
+    LispVal::undef()
+  },
+  SwapGoals: Exact(2) => {
+    let len = self.lc.goals.len();
+    let i = try1!(args[0].as_int(|n| n.to_usize()).ok_or("expected a number"));
+    let j = try1!(args[1].as_int(|n| n.to_usize()).ok_or("expected a number"));
+    match (i, j) {
+      (Some(i), Some(j)) if i < len && j < len => self.lc.goals.swap(i, j),
+      _ => try1!(Err(format!("goal index out of range (there are {} goals)", len)))
+    }
+    LispVal::undef()
+  },
+  MadeProgress: Exact(1) => {
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let (gs, hyps) = self.progress_snapshot();
+    self.stack.push(Stack::MadeProgress(gs, hyps));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  Now: Exact(1) => {
+    let i = try1!(self.lc.goals.iter().position(|g| g.is_goal()).ok_or("no goals"));
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let mut gs = mem::take(&mut self.lc.goals);
+    self.lc.goals.push(gs.remove(i));
+    self.stack.push(Stack::Now(sp1, gs));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  FocusOn: AtLeast(1) => {
+    let mut it = args.into_iter();
+    let pred = it.next().unwrap();
+    let (i, ty) = try1!(self.next_goal_from(0).ok_or("no goals"));
+    let sp = pred.fspan().map_or(sp2, |fsp| fsp.span);
+    self.stack.push(Stack::FocusOn(sp1, pred.clone(), it, i));
+    return Ok(State::App(sp1, sp, pred, vec![ty], [].iter()))
+  },
+  Try: Exact(1) => {
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let (lc, refs) = self.snapshot_lc();
+    self.stack.push(Stack::Try(lc, refs));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  Solve: Exact(1) => {
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let (lc, refs) = self.snapshot_lc();
+    self.stack.push(Stack::Solve(lc, refs));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
   SetCloseFn: AtLeast(0) => {
     let e = args.drain(..).next().unwrap_or_default();
     if e.is_def() && !e.is_proc() {try1!(Err("expected a procedure"))}
@@ -963,17 +2133,54 @@ make_builtins! { self, sp1, sp2, args,
   },
   LocalCtx: Exact(0) =>
     LispVal::list(self.lc.proof_order.iter().map(|a| LispVal::atom(a.0)).collect::<Vec<_>>()),
+  GetHyp: Exact(1) => {
+    let a = try1!(args[0].as_atom().ok_or("expected an atom"));
+    match self.lc.get_proof(a) {
+      None => LispVal::undef(),
+      Some((a, e, p)) => LispVal::list(vec![LispVal::atom(*a), e.clone(), p.clone()])
+    }
+  },
+  GoalContext: AtLeast(0) => {
+    let head = match &*args {
+      [] => None,
+      [t] => Some(try1!(t.as_atom().ok_or("expected an atom"))),
+      _ => try1!(Err("expected at most 1 argument")),
+    };
+    LispVal::list(self.lc.proof_order.iter().enumerate()
+      .filter(|&(i, (a, e, _))| self.lc.proofs.get(a) == Some(&i) &&
+        head.map_or(true, |t| e.head().and_then(|h| h.as_atom()) == Some(t)))
+      .map(|(_, (a, e, _))| LispVal::list(vec![LispVal::atom(*a), e.clone()]))
+      .collect::<Vec<_>>())
+  },
+  ClearHyp: Exact(1) => {
+    let a = try1!(args[0].as_atom().ok_or("expected an atom"));
+    if self.lc.remove_proof(a).is_none() {
+      try1!(Err(format!("unknown hypothesis '{}'", self.print(&a))))
+    }
+    LispVal::undef()
+  },
   ToExpr: Exact(1) => return Ok(State::Refine {
     sp: sp1, stack: vec![RStack::DeferGoals(mem::take(&mut self.lc.goals))],
     state: RState::RefineExpr {tgt: InferTarget::Unknown, e: args.swap_remove(0)}
   }),
-  Refine: AtLeast(0) => return Ok(State::Refine {
-    sp: sp1, stack: vec![],
-    state: RState::Goals {
-      gs: mem::take(&mut self.lc.goals).into_iter(),
-      es: args.into_iter()
+  Refine: AtLeast(0) => {
+    if args.first().and_then(|e| e.as_atom()) == Some(AtomID::TGT) {
+      if args.len() != 3 {try1!(Err("expected (refine :tgt e p)"))}
+      let mut args = args.drain(1..);
+      let (e, p) = (args.next().unwrap(), args.next().unwrap());
+      return Ok(State::Refine {
+        sp: sp1, stack: vec![RStack::DeferGoals(mem::take(&mut self.lc.goals)), RStack::Typed(p)],
+        state: RState::RefineExpr {tgt: InferTarget::Unknown, e}
+      })
     }
-  }),
+    return Ok(State::Refine {
+      sp: sp1, stack: vec![],
+      state: RState::Goals {
+        gs: mem::take(&mut self.lc.goals).into_iter(),
+        es: args.into_iter()
+      }
+    })
+  },
   Have: AtLeast(2) => {
     if args.len() > 3 {try1!(Err("invalid arguments"))}
     let mut args = args.drain(..);
@@ -994,10 +2201,352 @@ make_builtins! { self, sp1, sp2, args,
     };
     return Ok(State::Refine {sp: sp1, stack, state})
   },
+  Symm: Exact(0) => {
+    let t = try1!(self.rel_goal());
+    let (thm, _) = try1!(self.find_rel_lemma(t, &[(0, 1)], (1, 0)).ok_or_else(||
+      format!("no symmetry theorem found for '{}'", self.print(&t))));
+    let p = LispVal::list(vec![LispVal::atom(self.thms[thm].atom), LispVal::atom(AtomID::UNDER)]);
+    return Ok(State::Refine {
+      sp: sp1, stack: vec![],
+      state: RState::Goals {
+        gs: mem::take(&mut self.lc.goals).into_iter(),
+        es: vec![p].into_iter()
+      }
+    })
+  },
+  Trans: Exact(1) => {
+    let t = try1!(self.rel_goal());
+    let (thm, map) = try1!(self.find_rel_lemma(t, &[(0, 1), (1, 2)], (0, 2)).ok_or_else(||
+      format!("no transitivity theorem found for '{}'", self.print(&t))));
+    let mut p = vec![LispVal::atom(AtomID::BANG), LispVal::atom(self.thms[thm].atom)];
+    for i in 0..map.len() {
+      p.push(if i == map[1] {args[0].clone()} else {LispVal::atom(AtomID::UNDER)})
+    }
+    p.push(LispVal::atom(AtomID::UNDER));
+    p.push(LispVal::atom(AtomID::UNDER));
+    return Ok(State::Refine {
+      sp: sp1, stack: vec![],
+      state: RState::Goals {
+        gs: mem::take(&mut self.lc.goals).into_iter(),
+        es: vec![LispVal::list(p)].into_iter()
+      }
+    })
+  },
+  ExprDiff: Exact(2) => {
+    let mut path = vec![];
+    match Elaborator::expr_diff(&args[0], &args[1], &mut path) {
+      None => LispVal::undef(),
+      Some((a1, a2)) => LispVal::list(vec![
+        LispVal::list(path.into_iter().map(|i| LispVal::number(i.into())).collect::<Vec<_>>()),
+        a1, a2]),
+    }
+  },
+  ExprDiffPretty: Exact(2) => {
+    let mut path = vec![];
+    let mut out = String::new();
+    if Elaborator::expr_diff(&args[0], &args[1], &mut path).is_some() {
+      self.write_marked(&mut out, &args[0], &path);
+      out.push('\n');
+      self.write_marked(&mut out, &args[1], &path);
+    } else {
+      out = format!("{}\n{}", self.print(&args[0]), self.print(&args[1]))
+    }
+    LispVal::string(ArcString::new(out))
+  },
+  StartRecording: Exact(0) => {
+    self.recording = true;
+    self.recorded.clear();
+    LispVal::undef()
+  },
+  StopRecording: Exact(0) => {
+    self.recording = false;
+    LispVal::list(self.recorded.clone())
+  },
+  Replay: AtLeast(0) => {
+    let calls = match args.pop() {
+      None => self.recorded.clone(),
+      Some(e) if args.is_empty() => {
+        let mut calls = vec![];
+        if !e.clone().extend_into(usize::MAX, &mut calls) && !e.is_list() {
+          try1!(Err("expected a list"))
+        }
+        calls
+      }
+      _ => try1!(Err("expected 0 or 1 arguments")),
+    };
+    self.stack.push(Stack::Replay(sp1, calls.into_iter()));
+    LispVal::undef()
+  },
+  WithStepBudget: Exact(2) => {
+    let n = try1!(args[0].as_int(|n| n.to_usize().unwrap_or(usize::MAX)).ok_or("expected a number"));
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let old = self.budget;
+    let n = old.map_or(n, |o| o.min(n));
+    self.budget = Some(n);
+    self.stack.push(Stack::StepBudget(old, n));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  ProofScript: Exact(0) => {
+    let mut script = vec![LispVal::syntax(Syntax::Focus)];
+    for call in &self.recorded {
+      let mut u = Uncons::from(call.clone());
+      let mut e = vec![u.next().unwrap()];
+      e.extend(u.map(|e| LispVal::list(vec![LispVal::syntax(Syntax::Quote), e])));
+      script.push(LispVal::list(e))
+    }
+    LispVal::list(script)
+  },
+  Provable: AtLeast(1) => {
+    let mut thms = vec![];
+    for e in &args {
+      thms.push(try1!(e.as_atom().and_then(|a| self.thm(a)).ok_or("expected a theorem")))
+    }
+    let g = try1!(self.lc.goals.first().and_then(|g| g.goal_type()).ok_or("no goals"));
+    LispVal::bool(self.shallow_provable(&thms, &g, 3))
+  },
   Stat: Exact(0) => {print!(sp1, self.stat()); LispVal::undef()},
-  GetDecl: Exact(1) => {
+  GetStat: Exact(0) => self.get_stat(),
+  CurrentDecl: Exact(0) => self.cur_decl.map_or_else(LispVal::undef, LispVal::atom),
+  ExportState: Exact(0) => {
+    let fsp = self.fspan_base(sp1);
+    LispVal::string(ArcString::new(self.export_state(&fsp)))
+  },
+  AuditDeps: Exact(0) => LispVal::list(self.audit_deps().into_iter()
+    .map(|t| LispVal::atom(self.terms[t].atom)).collect::<Vec<_>>()),
+  UnusedDefs: Exact(0) => LispVal::list(self.unused_defs().into_iter()
+    .map(|t| LispVal::atom(self.terms[t].atom)).collect::<Vec<_>>()),
+  ThmsByHead: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let thms = self.elab.thms_by_head(t).to_vec();
+    LispVal::list(thms.into_iter().map(|t| LispVal::atom(self.thms[t].atom)).collect::<Vec<_>>())
+  },
+  GetDecl: AtLeast(1) => {
+    if args.len() > 2 {try1!(Err("expected 1 or 2 arguments"))}
+    let x = try1!(args[0].as_atom().ok_or("expected an atom"));
+    let eager = args.get(1).map_or(false, |e| e.truthy());
+    self.get_decl(args[0].fspan(), x, eager)
+  },
+  GetBinders: Exact(1) => {
+    let x = try1!(args[0].as_atom().ok_or("expected an atom"));
+    let bis = match self.data[x].decl {
+      Some(DeclKey::Term(t)) => &self.terms[t].args,
+      Some(DeclKey::Thm(t)) => &self.thms[t].args,
+      None => try1!(Err(format!("unknown term or theorem '{}'", self.print(&x)))),
+    };
+    let mut bvs = vec![];
+    LispVal::list(bis.iter().map(|&(a, ref ty)| {
+      let a = LispVal::atom(a.unwrap_or(AtomID::UNDER));
+      match *ty {
+        Type::Bound(s) => {
+          bvs.push(a.clone());
+          LispVal::list(vec![a, LispVal::bool(true), LispVal::atom(self.sorts[s].atom), LispVal::nil()])
+        }
+        Type::Reg(s, xs) => LispVal::list(vec![a, LispVal::bool(false),
+          LispVal::atom(self.sorts[s].atom), Environment::deps(&bvs, xs)]),
+      }
+    }).collect::<Vec<_>>())
+  },
+  ThmHyps: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| match self.data[a].decl {
+      Some(DeclKey::Thm(t)) => Some(t), _ => None
+    }).ok_or("expected a theorem"));
+    let heap = self.thm_heap(t);
+    LispVal::list(self.thms[t].hyps.iter().map(|(a, e)| LispVal::list(vec![
+      LispVal::atom(a.unwrap_or(AtomID::UNDER)),
+      self.expr_node(&heap, &mut None, e)
+    ])).collect::<Vec<_>>())
+  },
+  ThmConcl: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| match self.data[a].decl {
+      Some(DeclKey::Thm(t)) => Some(t), _ => None
+    }).ok_or("expected a theorem"));
+    let heap = self.thm_heap(t);
+    self.expr_node(&heap, &mut None, &self.thms[t].ret)
+  },
+  GetSort: Exact(1) => {
+    let x = try1!(args[0].as_atom().ok_or("expected an atom"));
+    self.get_sort(args[0].fspan(), x)
+  },
+  Declared: Exact(1) => {
     let x = try1!(args[0].as_atom().ok_or("expected an atom"));
-    self.get_decl(args[0].fspan(), x)
+    let ad = &self.data[x];
+    match (ad.decl, ad.sort) {
+      (Some(DeclKey::Term(_)), _) => LispVal::atom(AtomID::TERM),
+      (Some(DeclKey::Thm(_)), _) => LispVal::atom(AtomID::THM_DECL),
+      (None, Some(_)) => LispVal::atom(AtomID::SORT),
+      (None, None) => LispVal::undef(),
+    }
+  },
+  GetStmts: AtLeast(0) => {
+    let filter = match &*args {
+      [] => None,
+      [k] => match try1!(k.as_atom().ok_or("expected an atom")) {
+        k @ AtomID::SORT | k @ AtomID::DECL | k @ AtomID::GLOBAL => Some(k),
+        k => try1!(Err(format!("unknown statement kind '{}'", self.print(&k))))
+      },
+      _ => try1!(Err("expected 0 or 1 arguments")),
+    };
+    LispVal::list(self.stmts.iter().filter_map(|s| {
+      let k = match s {
+        StmtTrace::Sort(_) => AtomID::SORT,
+        StmtTrace::Decl(_) => AtomID::DECL,
+        StmtTrace::Global(_) => AtomID::GLOBAL,
+      };
+      if filter.map_or(true, |f| f == k) {
+        Some(LispVal::list(vec![LispVal::atom(k), LispVal::atom(s.atom())]))
+      } else {None}
+    }).collect::<Vec<_>>())
+  },
+  GetSpans: AtLeast(0) => {
+    let filter = match &*args {
+      [] => None,
+      [k] => match try1!(k.as_atom().ok_or("expected an atom")) {
+        k @ AtomID::SORT | k @ AtomID::TERM | k @ AtomID::THM_DECL | k @ AtomID::VAR |
+        k @ AtomID::GLOBAL | k @ AtomID::EXPR | k @ AtomID::PROOF | k @ AtomID::IMPORT |
+        k @ AtomID::CUSTOM | k @ AtomID::GOTO => Some(k),
+        k => try1!(Err(format!("unknown object kind '{}'", self.print(&k))))
+      },
+      _ => try1!(Err("expected 0 or 1 arguments")),
+    };
+    self.get_spans(filter)
+  },
+  GetNotation: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    self.get_notation(t)
+  },
+  GetPrecAssoc: Exact(1) => {
+    let p = try1!(args[0].as_int(|n| n.to_u32()).ok_or("expected a number"));
+    match p.and_then(|p| self.pe.prec_assoc.get(&p)) {
+      None => LispVal::undef(),
+      Some(&(_, r)) => LispVal::atom(if r {AtomID::RIGHT} else {AtomID::LEFT}),
+    }
+  },
+  AddDelimiters: Exact(2) => {
+    let ls = try1!(self.as_string(&args[0]));
+    let rs = try1!(self.as_string(&args[1]));
+    let f = |s: &[u8]| s.iter().copied().filter(|&c| !crate::parser::whitespace(c)).collect::<Vec<u8>>();
+    self.pe.add_delimiters(&f(ls.as_bytes()), &f(rs.as_bytes()));
+    LispVal::undef()
+  },
+  GetCoe: Exact(2) => {
+    let s1 = try1!(args[0].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    let s2 = try1!(args[1].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    if s1 == s2 {LispVal::nil()} else {
+      match self.pe.coes.get(&s1).and_then(|m| m.get(&s2)) {
+        None => LispVal::undef(),
+        Some(c) => {
+          let mut ts = vec![];
+          c.terms(&mut ts);
+          LispVal::list(ts.into_iter().map(|t| LispVal::atom(self.terms[t].atom)).collect::<Vec<_>>())
+        }
+      }
+    }
+  },
+  ApplyCoe: Exact(3) => {
+    let s1 = try1!(args[0].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    let s2 = try1!(args[1].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    let e = args.pop().unwrap();
+    if s1 == s2 {e} else {
+      let c = try1!(self.pe.coes.get(&s1).and_then(|m| m.get(&s2)).ok_or_else(||
+        format!("no coercion from {} to {}", self.print(&s1), self.print(&s2))));
+      self.apply_coe(&e.fspan(), c, e)
+    }
+  },
+  AddPrefix: Exact(3) => {
+    let tk = try1!(self.as_string(&args[0]));
+    let t = try1!(args[1].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let prec = try1!(self.as_prec(&args[2]));
+    let fsp = self.fspan_base(sp1);
+    self.add_simple_nota(fsp, tk, t, prec, None)?;
+    LispVal::undef()
+  },
+  AddInfix: Exact(4) => {
+    let tk = try1!(self.as_string(&args[0]));
+    let t = try1!(args[1].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let prec = try1!(self.as_prec(&args[2]));
+    let right = match args[3].as_atom() {
+      Some(AtomID::LEFT) => false,
+      Some(AtomID::RIGHT) => true,
+      _ => try1!(Err("expected 'left or 'right")),
+    };
+    let fsp = self.fspan_base(sp1);
+    self.add_simple_nota(fsp, tk, t, prec, Some(right))?;
+    LispVal::undef()
+  },
+  AddNotation: AtLeast(3) => {
+    if args.len() > 4 {try1!(Err("expected 3 or 4 arguments"))}
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let prec = try1!(self.as_prec(&args[1]));
+    let assoc = match args.get(3).map(|e| e.as_atom()) {
+      None => None,
+      Some(Some(AtomID::LEFT)) => Some(false),
+      Some(Some(AtomID::RIGHT)) => Some(true),
+      Some(_) => try1!(Err("expected 'left or 'right")),
+    };
+    let fsp = self.fspan_base(sp1);
+    self.add_notation(fsp, t, prec, &args[2], assoc)?;
+    LispVal::undef()
+  },
+  ImportEnv: Exact(1) => {
+    let f = try1!(self.as_string(&args[0]));
+    let path = self.path.path().parent().map_or_else(|| PathBuf::from(&*f), |p| p.join(&*f));
+    let env = try1!(path.canonicalize().ok().and_then(|p| self.imported.get(&p).cloned())
+      .ok_or_else(|| format!("file '{}' has not been imported", f)));
+    let mut errors = vec![];
+    let r = self.env.merge(&env, sp1, &mut errors);
+    for e in errors { self.report(e) }
+    r?;
+    LispVal::undef()
+  },
+  AddCoe: Exact(3) => {
+    let s1 = try1!(args[0].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    let s2 = try1!(args[1].as_atom().and_then(|s| self.data[s].sort).ok_or("expected a sort"));
+    let t = try1!(args[2].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    self.check_term_nargs(sp1, t, 1)?;
+    let fsp = self.fspan_base(sp1);
+    if let Some(w) = self.add_coe(s1, s2, fsp, t)? { self.report(w) }
+    LispVal::undef()
+  },
+  ExprDeps: Exact(1) => self.expr_deps(sp1, &args[0])?,
+  GetTermDeps: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let td = &self.terms[t];
+    let bvs = td.args.iter().filter(|(_, ty)| ty.bound())
+      .map(|&(a, _)| LispVal::atom(a.unwrap_or(AtomID::UNDER))).collect::<Vec<_>>();
+    Environment::deps(&bvs, td.ret.1)
+  },
+  UnfoldDef: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    args.remove(0);
+    try1!(self.unfold_def(t, args))
+  },
+  UnfoldMut: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let es = args.split_off(1);
+    let e = try1!(self.unfold_def(t, es.clone()));
+    let p = LispVal::list(vec![LispVal::atom(AtomID::UNFOLD),
+      LispVal::atom(self.terms[t].atom), LispVal::list(es), e.clone()]);
+    LispVal::list(vec![e, p])
+  },
+  MkRefl: Exact(1) => LispVal::list(vec![LispVal::atom(AtomID::REFL), args.pop().unwrap()]),
+  MkCong: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.term(a)).ok_or("expected a term"));
+    let n = self.terms[t].args.len();
+    if args.len() != n + 1 {try1!(Err(format!("expected {} arguments", n)))}
+    args[0] = LispVal::atom(self.terms[t].atom);
+    args.insert(0, LispVal::atom(AtomID::CONG));
+    LispVal::list(args)
+  },
+  ConvSides: Exact(1) => {
+    let lhs = try1!(self.conv_side(&args[0], false));
+    let rhs = try1!(self.conv_side(&args[0], true));
+    LispVal::list(vec![lhs, rhs])
+  },
+  Subst: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.thm(a)).ok_or("expected a theorem"));
+    args.remove(0);
+    try1!(self.subst_thm(t, args))
   },
   AddDecl: AtLeast(4) => {
     let fsp = self.fspan_base(sp1);
@@ -1017,6 +2566,112 @@ make_builtins! { self, sp1, sp2, args,
     let fsp = self.fspan_base(sp1);
     return self.add_thm(fsp, &args)
   },
+  VerifyProof: AtLeast(3) => {
+    let fsp = self.fspan_base(sp1);
+    self.verify_proof(fsp, &args)?;
+    LispVal::bool(true)
+  },
+  ProofSize: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| match self.data[a].decl {
+      Some(DeclKey::Thm(t)) => Some(t), _ => None
+    }).ok_or("expected a theorem"));
+    let (heap, nodes) = try1!(self.proof_size(t).ok_or_else(||
+      format!("theorem '{}' has no proof", self.print(&self.thms[t].atom))));
+    LispVal::list(vec![LispVal::number(heap.into()), LispVal::number(nodes.into())])
+  },
+  ExportMmu: Exact(0) => {
+    let mut out = vec![];
+    try1!(FrozenEnv::with_frozen(&mut self.env, |fe| fe.export_mmu(&mut out))
+      .map_err(|e| format!("export failed: {}", e)));
+    LispVal::string(ArcString::new(String::from_utf8_lossy(&out).into_owned()))
+  },
+  ExportDeclMmu: Exact(1) => {
+    let a = try1!(args[0].as_atom().ok_or("expected an atom"));
+    let ad = &self.data[a];
+    let st = if ad.decl.is_some() { StmtTrace::Decl(a) }
+      else if ad.sort.is_some() { StmtTrace::Sort(a) }
+      else { try1!(Err(format!("unknown declaration '{}'", self.print(&a)))) };
+    let mut out = vec![];
+    try1!(FrozenEnv::with_frozen(&mut self.env, |fe| fe.export_mmu_stmt(st, &mut out))
+      .map_err(|e| format!("export failed: {}", e)));
+    LispVal::string(ArcString::new(String::from_utf8_lossy(&out).into_owned()))
+  },
+  DumpProofDag: Exact(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| match self.data[a].decl {
+      Some(DeclKey::Thm(t)) => Some(t), _ => None
+    }).ok_or("expected a theorem"));
+    let s = try1!(self.dump_proof_dag(t).ok_or_else(||
+      format!("theorem '{}' has no proof", self.print(&self.thms[t].atom))));
+    print!(sp1, s);
+    LispVal::undef()
+  },
+  PrepareStmt: AtLeast(2) => {
+    let fsp = self.fspan_base(sp1);
+    let stmt = self.prepare_stmt(fsp, &args)?;
+    let h = self.prepared.0;
+    self.prepared.0 += 1;
+    self.prepared.1.insert(h, stmt);
+    LispVal::number(h.into())
+  },
+  CheckPrepared: Exact(2) => {
+    let stmt = try1!(args[0].as_int(|n| n.to_usize()).flatten()
+      .and_then(|h| self.prepared.1.get(&h)).ok_or("expected a prepared statement handle"));
+    self.check_prepared(stmt, &args[1])?;
+    LispVal::bool(true)
+  },
+  ProofStat: AtLeast(3) => {
+    let fsp = self.fspan_base(sp1);
+    let (total, shared) = self.verify_proof(fsp, &args)?;
+    let ratio = if total == 0 {LispVal::number(BigInt::zero())} else {
+      LispVal::ratio(BigRational::new(shared.into(), total.into()))
+    };
+    LispVal::list(vec![LispVal::number(total.into()), LispVal::number(shared.into()), ratio])
+  },
+  CheckDv: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| match self.data[a].decl {
+      Some(DeclKey::Thm(t)) => Some(t), _ => None
+    }).ok_or("expected a theorem"));
+    let td = &self.thms[t];
+    if td.args.len() + 1 != args.len() {
+      try1!(Err(format!("expected {} arguments", td.args.len())))
+    }
+    let deps = args[1..].iter().map(|e| {
+      let mut out = HashSet::new();
+      self.bound_vars(e, &mut out);
+      out
+    }).collect::<Vec<_>>();
+    let bad = dv_pairs(&td.args).into_iter()
+      .filter(|&(i, j)| !deps[i].is_disjoint(&deps[j]))
+      .map(|(i, j)| LispVal::list(vec![
+        LispVal::atom(td.args[i].0.unwrap_or(AtomID::UNDER)),
+        LispVal::atom(td.args[j].0.unwrap_or(AtomID::UNDER))]))
+      .collect::<Vec<_>>();
+    if bad.is_empty() {LispVal::bool(true)} else {LispVal::list(bad)}
+  },
+  WithNamespace: Exact(2) => {
+    let ns = try1!(self.as_string(&args[0]));
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let old = mem::replace(&mut self.namespace, Some(ns));
+    self.stack.push(Stack::RestoreNamespace(old));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  OpenNamespace: Exact(2) => {
+    let ns = try1!(self.as_string(&args[0]));
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let n = self.open_namespaces.len();
+    self.stack.push(Stack::CloseNamespace(n));
+    self.open_namespaces.push(ns);
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  WithEnvSnapshot: Exact(1) => {
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let snap = Box::new(self.env.snapshot());
+    self.stack.push(Stack::EnvSnapshot(snap, self.prepared.0));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
   NewDummy: AtLeast(1) => {
     if args.len() > 2 {try1!(Err("expected 1 or 2 armuments"))}
     let (x, s) = match args.get(1) {
@@ -1035,6 +2690,25 @@ make_builtins! { self, sp1, sp2, args,
     self.lc.vars.insert(x, (true, InferSort::Bound(sort)));
     LispVal::atom(x)
   },
+  IsDummy: Exact(1) => {
+    let a = try1!(args[0].as_atom().ok_or("expected an atom"));
+    LispVal::bool(self.lc.vars.get(&a).map_or(false, |&(dummy, _)| dummy))
+  },
+  MustBound: Exact(1) => {
+    let a = try1!(args[0].as_atom().ok_or("expected an atom"));
+    match self.lc.vars.get(&a) {
+      Some(&(_, InferSort::Unknown {must_bound, ..})) => LispVal::bool(must_bound),
+      _ => LispVal::undef(),
+    }
+  },
+  GetDummies: Exact(0) => {
+    let mut ds = self.lc.vars.iter().filter(|(_, &(dummy, _))| dummy)
+      .map(|(&a, (_, is))| (a, is.sort())).collect::<Vec<_>>();
+    ds.sort_by_key(|&(a, _)| a);
+    LispVal::list(ds.into_iter().map(|(a, s)| LispVal::list(vec![LispVal::atom(a),
+      s.map_or_else(LispVal::undef, |s| LispVal::atom(self.sorts[s].atom))])).collect::<Vec<_>>())
+  },
+  MaxBoundVars: Exact(0) => LispVal::number(MAX_BOUND_VARS.into()),
   SetReporting: AtLeast(1) => {
     if args.len() == 1 {
       if let Some(b) = args[0].as_bool() {
@@ -1052,6 +2726,37 @@ make_builtins! { self, sp1, sp2, args,
     } else {try1!(Err("invalid arguments"))}
     LispVal::undef()
   },
+  SetReportDedup: Exact(1) => {
+    let b = try1!(args[0].as_bool().ok_or("expected a boolean"));
+    if b != self.seen_reports.is_some() {
+      self.seen_reports = if b {Some(HashSet::new())} else {None}
+    }
+    LispVal::undef()
+  },
+  WithReporting: Exact(2) => {
+    let mode = try1!(self.report_mode(&args[0]));
+    let proc = args.pop().unwrap();
+    let sp = proc.fspan().map_or(sp2, |fsp| fsp.span);
+    let old = mem::replace(&mut self.reporting, mode);
+    self.stack.push(Stack::RestoreReporting(old));
+    return Ok(State::App(sp1, sp, proc, vec![], [].iter()))
+  },
+  Deprecate: AtLeast(1) => {
+    let t = try1!(args[0].as_atom().and_then(|a| self.thm(a)).ok_or("expected a theorem"));
+    let msg = match args.get(1) {
+      None => None,
+      Some(e) if args.len() == 2 => Some(try1!(self.as_string(e))),
+      _ => try1!(Err("expected 1 or 2 arguments")),
+    };
+    self.deprecated.insert(t, msg);
+    LispVal::undef()
+  },
+  SetNamePolicy: Exact(1) => {
+    let f = args.pop().unwrap();
+    if f.is_def() && !f.is_proc() {try1!(Err("expected a procedure"))}
+    self.name_policy = f;
+    LispVal::undef()
+  },
   CheckProofs: Exact(1) => {
     if let Some(b) = args[0].as_bool() {
       self.check_proofs = b;
@@ -1079,8 +2784,49 @@ impl<'a> Evaluator<'a> {
     }
   }
 
-  #[allow(clippy::never_loop)]
   fn run(&mut self, mut active: State<'a>) -> Result<LispVal> {
+    loop {
+      match self.run_loop(active) {
+        Ok(ret) => return Ok(ret),
+        Err(err) => match self.unwind() {
+          Some(st) => active = st,
+          None => return Err(err)
+        }
+      }
+    }
+  }
+
+  /// Unwind the stack after an error, running any cleanup actions, until we reach
+  /// a `try` frame that catches the error. Timeouts and cancellation are not caught.
+  fn unwind(&mut self) -> Option<State<'a>> {
+    let fatal = self.cancel.load(Ordering::Relaxed) ||
+      self.cur_timeout.map_or(false, |t| t < Instant::now());
+    while let Some(s) = self.stack.pop() {
+      match s {
+        Stack::Drop(n) => self.ctx.truncate(n),
+        Stack::Ret(fsp, _, old, _) => {self.file = fsp.file; self.ctx = old}
+        Stack::MatchCont(_, _, _, valid) =>
+          if let Err(valid) = Rc::try_unwrap(valid) {valid.set(false)},
+        Stack::RestoreReporting(mode) => self.reporting = mode,
+        Stack::RestoreNamespace(ns) => self.namespace = ns,
+        Stack::CloseNamespace(n) => self.open_namespaces.truncate(n),
+        Stack::EnvSnapshot(snap, n) => self.rollback_env(*snap, n),
+        Stack::StepBudget(old, n) => {
+          let used = self.budget.map_or(0, |b| n - b);
+          self.budget = old.map(|o| o - used);
+        }
+        Stack::Try(lc, refs) if !fatal => {
+          self.restore_lc(lc, refs);
+          return Some(State::Ret(LispVal::undef()))
+        }
+        _ => {}
+      }
+    }
+    None
+  }
+
+  #[allow(clippy::never_loop)]
+  fn run_loop(&mut self, mut active: State<'a>) -> Result<LispVal> {
     macro_rules! throw {($sp:expr, $e:expr) => {{
       let err = $e;
       return Err(self.err(Some(($sp, false)), err))
@@ -1102,6 +2848,10 @@ impl<'a> Evaluator<'a> {
           return Err(self.err(None, "cancelled"))
         }
       }
+      if let Some(n) = self.budget {
+        if n == 0 {return Err(self.err(None, "step budget exceeded"))}
+        self.budget = Some(n - 1);
+      }
       if self.stack.len() >= 1024 {
         return Err(self.err(None, format!("stack overflow: {:#?}", self.ctx)))
       }
@@ -1125,7 +2875,10 @@ impl<'a> Evaluator<'a> {
           &IR::Local(i) => State::Ret(self.ctx[i].clone()),
           &IR::Global(sp, a) => State::Ret(match &self.data[a] {
             AtomData {name, lisp: None, ..} => match BuiltinProc::from_str(name) {
-              Err(_) => throw!(sp, format!("Reference to unbound variable '{}'", name)),
+              Err(_) => match self.resolve_open(a, |_, ad| ad.lisp.as_ref().map(|(_, e)| e.clone())) {
+                Some(e) => e,
+                None => throw!(sp, format!("Reference to unbound variable '{}'", name)),
+              },
               Ok(p) => {
                 let s = name.clone();
                 let a = self.get_atom(&s);
@@ -1265,6 +3018,68 @@ impl<'a> Evaluator<'a> {
           },
           Some(Stack::Refine {sp, stack}) =>
             State::Refine {sp, stack, state: RState::Ret(ret)},
+          Some(Stack::Replay(sp, mut it)) => match it.next() {
+            None => State::Ret(LispVal::undef()),
+            Some(call) => {
+              let mut u = Uncons::from(call.clone());
+              let p = u.next().and_then(|f| f.as_atom())
+                .and_then(|a| BuiltinProc::from_str(&self.data[a].name).ok())
+                .filter(|p| p.is_tactic());
+              match p {
+                None => throw!(sp, format!("replay: invalid tactic call {}", self.print(&call))),
+                Some(p) => push!(Replay(sp, it);
+                  App(sp, sp, LispVal::proc(Proc::Builtin(p)), u.collect(), [].iter()))
+              }
+            }
+          },
+          Some(Stack::Record(call)) => {self.recorded.push(call); State::Ret(ret)}
+          Some(Stack::GoalsClosed(gs)) => State::Ret(LispVal::list(gs.into_iter()
+            .filter(|g| !g.is_goal() || !self.lc.goals.iter().any(|g2| g2.ptr_eq(g)))
+            .collect::<Vec<_>>())),
+          Some(Stack::Now(sp, gs)) => {
+            if self.lc.goals.iter().any(|g| g.is_goal()) {
+              let stat = self.stat();
+              throw!(sp, format!("now: goal was not solved\n\n{}", stat))
+            }
+            self.lc.set_goals(gs);
+            State::Ret(LispVal::undef())
+          }
+          Some(Stack::FocusOn(sp, pred, it, i)) => if ret.truthy() && i < self.lc.goals.len() {
+            let g = self.lc.goals.remove(i);
+            self.lc.goals.insert(0, g);
+            let gs = self.lc.goals.drain(1..).collect();
+            push!(Focus(sp, true, gs), FocusOnTacs(sp, it); Ret(LispVal::undef()))
+          } else {
+            match self.next_goal_from(i + 1) {
+              None => throw!(sp, "focus-on: no goal satisfies the predicate"),
+              Some((i, ty)) => push!(FocusOn(sp, pred.clone(), it, i);
+                App(sp, sp, pred, vec![ty], [].iter()))
+            }
+          },
+          Some(Stack::FocusOnTacs(sp, mut it)) => match it.next() {
+            None => State::Ret(LispVal::undef()),
+            Some(f) => push!(FocusOnTacs(sp, it); App(sp, sp, f, vec![], [].iter()))
+          },
+          Some(Stack::Try(_, _)) => State::Ret(ret),
+          Some(Stack::Solve(lc, refs)) => {
+            let solved = !self.lc.goals.iter().any(|g| g.is_goal());
+            if !solved {self.restore_lc(lc, refs)}
+            State::Ret(LispVal::bool(solved))
+          }
+          Some(Stack::MadeProgress(gs, hyps)) =>
+            State::Ret(LispVal::bool((gs, hyps) != self.progress_snapshot())),
+          Some(Stack::RestoreReporting(mode)) => {self.reporting = mode; State::Ret(ret)}
+          Some(Stack::RestoreNamespace(ns)) => {self.namespace = ns; State::Ret(ret)}
+          Some(Stack::CloseNamespace(n)) => {self.open_namespaces.truncate(n); State::Ret(ret)}
+          Some(Stack::EnvSnapshot(snap, n)) => {
+            if ret.as_atom() == Some(AtomID::ROLLBACK) { self.rollback_env(*snap, n) }
+            State::Ret(ret)
+          }
+          Some(Stack::StepBudget(old, n)) => {
+            let used = self.budget.map_or(0, |b| n - b);
+            self.budget = old.map(|o| o - used);
+            State::Ret(ret)
+          }
           Some(Stack::Have(sp, x)) => {
             let e = self.infer_type(sp, &ret)?;
             let span = try_get_span(&self.fspan(sp), &x);
@@ -1438,7 +3253,7 @@ impl<'a> Evaluator<'a> {
           let res = self.elab.run_refine(self.orig_span, &mut stack, state)
             .map_err(|e| self.err(Some((e.pos, true)), e.kind.msg()))?;
           match res {
-            RefineResult::Ret(e) => {self.lc.clean_mvars(); State::Ret(e)}
+            RefineResult::Ret(e) => {self.lc.maybe_clean_mvars(); State::Ret(e)}
             RefineResult::RefineExtraArgs(tgt, e, u) => {
               let mut args = vec![LispVal::proc(Proc::RefineCallback), tgt.clone(), e];
               for e in u {args.push(e)}
@@ -1458,4 +3273,24 @@ impl<'a> Evaluator<'a> {
       }
     }
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, atomic::AtomicBool};
+  use crate::lined_string::LinedString;
+  use super::*;
+
+  #[test]
+  fn tail_deep_dotted_list() {
+    let (_, ast) = crate::parser::parse(Arc::new(LinedString::from(String::new())), None);
+    let path: FileRef = std::env::current_dir().unwrap().join("test.mm1").into();
+    let elab = Elaborator::new(Arc::new(ast), path, false, Arc::new(AtomicBool::new(false)));
+    let mut e = LispVal::list(vec![LispVal::number(1.into()), LispVal::number(2.into())]);
+    for _ in 0..1_000_000 { e = LispVal::dotted_list(vec![], e) }
+    let r = elab.tail(&e).unwrap();
+    assert_eq!(r, LispVal::list(vec![LispVal::number(2.into())]));
+    // Dropping the list recursively would overflow the stack, which is not what we are testing
+    mem::forget(e);
+  }
+}
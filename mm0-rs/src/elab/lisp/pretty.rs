@@ -62,8 +62,10 @@ impl LispKind {
       LispKind::Goal(_) => false,
       LispKind::Atom(_) |
       LispKind::MVar(_, _) |
+      LispKind::WeakRef(_) |
       LispKind::Proc(_) |
       LispKind::Number(_) |
+      LispKind::Ratio(_) |
       LispKind::String(_) |
       LispKind::Bool(_) |
       LispKind::Syntax(_) |
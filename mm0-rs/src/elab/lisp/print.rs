@@ -156,11 +156,16 @@ impl EnvDisplay for LispKind {
       LispKind::List(es) => list(es, None, true, fe, f),
       LispKind::Annot(_, e) => e.fmt(fe, f),
       LispKind::Number(n) => n.fmt(f),
+      LispKind::Ratio(q) => q.fmt(f),
       LispKind::String(s) => write!(f, "{:?}", s),
       LispKind::Bool(true) => "#t".fmt(f),
       LispKind::Bool(false) => "#f".fmt(f),
       LispKind::Syntax(s) => s.fmt(f),
       LispKind::Undef => write!(f, "#undef"),
+      LispKind::WeakRef(w) => match w.upgrade() {
+        Some(_) => write!(f, "#[weak-ref]"),
+        None => write!(f, "#[weak-ref dead]"),
+      },
       LispKind::Proc(Proc::Builtin(p)) => p.fmt(f),
       LispKind::Proc(Proc::Lambda {pos: ProcPos::Unnamed(pos), ..}) => {
         let r = fe.source.to_pos(pos.span.start);
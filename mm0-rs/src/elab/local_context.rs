@@ -18,7 +18,7 @@ use crate::util::*;
 /// but is known to be bound, `y` is not declared at all but known to be a bound non-dummy,
 /// and `z` is not declared and must be a bound dummy of type `var` (assuming
 /// that `all` has type `var` for its first argument).
-#[derive(Debug, DeepSizeOf)]
+#[derive(Clone, Debug, DeepSizeOf)]
 pub enum InferSort {
   /// This is a declared bound variable with the given sort.
   Bound(SortID),
@@ -68,7 +68,7 @@ impl InferSort {
 
 /// The local context is the collection of proof-local data. This is manipulated
 /// by lisp tactics in order to keep track of the proof state and eventually produce a proof.
-#[derive(Default, Debug, DeepSizeOf)]
+#[derive(Default, Clone, Debug, DeepSizeOf)]
 pub struct LocalContext {
   /// The collection of local variables. The key is the name of the variable, and the
   /// value is `(dummy, is)` where `dummy` is true if this is a dummy variable
@@ -85,6 +85,12 @@ pub struct LocalContext {
   /// metavariables. The `usize` field of a `MVar` will refer to the variable's position in
   /// this list.
   pub mvars: Vec<LispVal>,
+  /// The number of metavariables in `mvars` that have been assigned since the last call
+  /// to `clean_mvars`. This is used by `maybe_clean_mvars` to avoid traversing the whole
+  /// list after every refine step, so every assignment should increment it: unification
+  /// in `refine` (including `assign-mvar`), `set!` on a metavariable, and variable
+  /// sort inference.
+  pub mvars_assigned: usize,
   /// The list of goals, or holes in the proof. This is the main user-facing state of a proof.
   /// This can be manipulated by user code, but the builtin tactics will manage this list
   /// automatically. When the set of goals is empty, the proof is complete.
@@ -122,6 +128,7 @@ impl LocalContext {
     self.vars.clear();
     self.var_order.clear();
     self.mvars.clear();
+    self.mvars_assigned = 0;
     self.goals.clear();
     self.proofs.clear();
     self.proof_order.clear();
@@ -171,6 +178,7 @@ impl LocalContext {
   /// Remove all metavariables that are already assigned, and renumber those
   /// that remain, so that the printed names `?a`, `?b` etc. stay simple.
   pub fn clean_mvars(&mut self) {
+    self.mvars_assigned = 0;
     let mut i = 0;
     self.mvars.retain(|e| e.as_ref_(|e| {
       e.unwrapped_mut(|e| {
@@ -188,6 +196,13 @@ impl LocalContext {
     }).unwrap())
   }
 
+  /// Call `clean_mvars` if more than half of the metavariables in `mvars` are known
+  /// to be assigned. This keeps the amortized cost of cleanup constant per assignment,
+  /// rather than linear in the number of metavariables.
+  pub fn maybe_clean_mvars(&mut self) {
+    if 2 * self.mvars_assigned > self.mvars.len() {self.clean_mvars()}
+  }
+
   /// Get a subproof by name.
   pub fn get_proof(&self, a: AtomID) -> Option<&(AtomID, LispVal, LispVal)> {
     self.proofs.get(&a).map(|&i| &self.proof_order[i])
@@ -198,6 +213,22 @@ impl LocalContext {
     self.proofs.insert(a, self.proof_order.len());
     self.proof_order.push((a, e, p));
   }
+
+  /// Remove a subproof, returning it if it was present. The entry is removed from
+  /// `proof_order`, and the indexes of all later entries are shifted down to match.
+  /// If the removed subproof shadowed an earlier one with the same name, the earlier
+  /// subproof becomes visible again.
+  pub fn remove_proof(&mut self, a: AtomID) -> Option<(AtomID, LispVal, LispVal)> {
+    let i = self.proofs.remove(&a)?;
+    let res = self.proof_order.remove(i);
+    for j in self.proofs.values_mut() {
+      if *j > i {*j -= 1}
+    }
+    if let Some(j) = self.proof_order.iter().rposition(|p| p.0 == a) {
+      self.proofs.insert(a, j);
+    }
+    Some(res)
+  }
 }
 
 #[repr(C)]
@@ -429,6 +460,14 @@ struct BuildArgs {
 
 pub(crate) const MAX_BOUND_VARS: usize = 55;
 
+/// The error for exceeding [`MAX_BOUND_VARS`] when adding the bound variable `a`.
+///
+/// [`MAX_BOUND_VARS`]: constant.MAX_BOUND_VARS.html
+pub(crate) fn too_many_bound_vars(env: &Environment, sp: Span, a: Option<AtomID>) -> ElabError {
+  ElabError::new_e(sp, format!("too many bound variables (max {}), while adding '{}'",
+    MAX_BOUND_VARS, a.map_or("_", |a| &*env.data[a].name)))
+}
+
 impl BuildArgs {
   fn push_bound(&mut self, a: Option<AtomID>) -> Option<()> {
     if self.size >= MAX_BOUND_VARS {return None}
@@ -458,46 +497,53 @@ impl BuildArgs {
       InferSort::Unknown {..} => unreachable!(),
     }
   }
-  fn push_dummies(&mut self, vars: &HashMap<AtomID, (bool, InferSort)>) -> Option<()> {
+  fn push_dummies(&mut self, vars: &HashMap<AtomID, (bool, InferSort)>) -> StdResult<(), AtomID> {
     for (&a, is) in vars {
       if let (true, InferSort::Bound {..}) = is {
-        self.push_bound(Some(a))?
+        self.push_bound(Some(a)).ok_or(a)?
       }
     }
-    Some(())
+    Ok(())
   }
 
   fn expr_deps(&self, env: &Environment, e: &LispKind) -> u64 {
+    self.try_expr_deps(env, e).expect("expression should be elaborated")
+  }
+
+  /// Like `expr_deps`, but returns `None` if the expression contains metavariables or
+  /// unknown variables, or is not well formed.
+  fn try_expr_deps(&self, env: &Environment, e: &LispKind) -> Option<u64> {
     e.unwrapped(|r| match r {
-      &LispKind::Atom(a) => self.map[&a],
-      LispKind::List(es) if !es.is_empty() =>
-        if let Some(tid) = es[0].as_atom().and_then(|a| env.term(a)) {
-          let tdef = &env.terms[tid];
-          let mut argbv = Vec::new();
-          let mut out = 0;
-          for ((_, ty), e) in tdef.args.iter().zip(&es[1..]) {
-            let mut n = self.expr_deps(env, e);
-            match ty {
-              EType::Bound(_) => argbv.push(n),
-              &EType::Reg(_, deps) => {
-                let mut i = 1;
-                for &arg in &argbv {
-                  if deps & i != 0 { n &= !arg }
-                  i *= 2;
-                }
-                out |= n;
+      &LispKind::Atom(a) => self.map.get(&a).copied(),
+      LispKind::List(es) if !es.is_empty() => {
+        let tid = es[0].as_atom().and_then(|a| env.term(a))?;
+        let tdef = &env.terms[tid];
+        if tdef.args.len() + 1 != es.len() {return None}
+        let mut argbv = Vec::new();
+        let mut out = 0;
+        for ((_, ty), e) in tdef.args.iter().zip(&es[1..]) {
+          let mut n = self.try_expr_deps(env, e)?;
+          match ty {
+            EType::Bound(_) => argbv.push(n),
+            &EType::Reg(_, deps) => {
+              let mut i = 1;
+              for &arg in &argbv {
+                if deps & i != 0 { n &= !arg }
+                i *= 2;
               }
+              out |= n;
             }
           }
-          let deps = tdef.ret.1;
-          let mut i = 1;
-          for arg in argbv {
-            if deps & i != 0 { out |= arg }
-            i *= 2;
-          }
-          out
-        } else {unreachable!()},
-      _ => unreachable!()
+        }
+        let deps = tdef.ret.1;
+        let mut i = 1;
+        for arg in argbv {
+          if deps & i != 0 { out |= arg }
+          i *= 2;
+        }
+        Some(out)
+      }
+      _ => None
     })
   }
 }
@@ -582,7 +628,8 @@ impl Elaborator {
     ElabTermMut::new(self, sp).expr(e, tgt)
   }
 
-  fn infer_sort(&self, sp: Span, e: &LispKind) -> Result<SortID> {
+  /// Get the sort of the expression `e`, which should be a variable or a term application.
+  pub(crate) fn infer_sort(&self, sp: Span, e: &LispKind) -> Result<SortID> {
     ElabTerm::new(self, sp).infer_sort(e)
   }
 
@@ -618,6 +665,7 @@ impl Elaborator {
               if let LispKind::Ref(m) = &**e {
                 *m.get_mut() = val;
               } else {unreachable!()}
+              self.lc.mvars_assigned += 1;
             }
             let new2 = if (dummy && *new) || must_bound {
               *is = InferSort::Bound(sort);
@@ -709,7 +757,7 @@ impl Elaborator {
         let mut ba = BuildArgs::default();
         for &(sp, a, ref is) in &self.lc.var_order {
           let ty = ba.push_var(&self.lc.vars, a, is).ok_or_else(||
-            ElabError::new_e(sp, format!("too many bound variables (max {})", MAX_BOUND_VARS)))?;
+            too_many_bound_vars(&self.env, sp, a))?;
           args.push((a, ty));
         }
         let (ret, val) = match val {
@@ -720,8 +768,8 @@ impl Elaborator {
           },
           Some((sp, val)) => {
             let s = self.infer_sort(sp, &val)?;
-            if ba.push_dummies(&self.lc.vars).is_none() {
-              return Err(ElabError::new_e(sp, format!("too many bound variables (max {})", MAX_BOUND_VARS)))
+            if let Err(a) = ba.push_dummies(&self.lc.vars) {
+              return Err(too_many_bound_vars(&self.env, sp, Some(a)))
             }
             let deps = ba.expr_deps(&self.env, &val);
             let val = {
@@ -760,6 +808,7 @@ impl Elaborator {
         };
         let tid = self.env.add_term(atom, t.span.clone(), || t).map_err(|e| e.into_elab_error(d.id))?;
         self.spans.insert(d.id, ObjectKind::Term(tid, d.id));
+        self.check_name_policy(d.id, atom)?;
       }
       DeclKind::Axiom | DeclKind::Thm => {
         if d.val.is_none() {
@@ -793,7 +842,7 @@ impl Elaborator {
         let mut ba = BuildArgs::default();
         for &(sp, a, ref is) in &self.lc.var_order {
           let ty = ba.push_var(&self.lc.vars, a, is).ok_or_else(||
-            ElabError::new_e(sp, format!("too many bound variables (max {})", MAX_BOUND_VARS)))?;
+            too_many_bound_vars(&self.env, sp, a))?;
           args.push((a, ty));
         }
         let mut de = Dedup::new(&args);
@@ -846,6 +895,7 @@ impl Elaborator {
         };
         let tid = self.env.add_thm(atom, t.span.clone(), || t).map_err(|e| e.into_elab_error(d.id))?;
         self.spans.insert(d.id, ObjectKind::Thm(tid));
+        self.check_name_policy(d.id, atom)?;
       }
     }
     self.spans.lc = Some(mem::take(&mut self.lc));
@@ -882,6 +932,22 @@ impl AwaitingProof {
   }
 }
 
+/// A theorem statement that has been elaborated by [`prepare_stmt`], which is used by
+/// the `(prepare-stmt)` and `(check-prepared)` user-level functions to check several
+/// proofs against the same statement. The statement is stored as a `Dedup<ExprHash>`,
+/// and each proof attempt starts from a fresh copy obtained from [`Dedup::map_proof`].
+///
+/// [`prepare_stmt`]: ../struct.Elaborator.html#method.prepare_stmt
+/// [`Dedup::map_proof`]: ../proof/struct.Dedup.html#method.map_proof
+#[derive(Debug)]
+pub struct PreparedStmt {
+  lc: LocalContext,
+  de: Dedup<ExprHash>,
+  var_map: HashMap<AtomID, usize>,
+  is: Vec<(Option<AtomID>, usize, LispVal)>,
+  fsp: FileSpan,
+}
+
 #[derive(Debug)]
 struct ThmVal {
   de: Dedup<ProofHash>,
@@ -951,8 +1017,7 @@ impl Elaborator {
           None => {
             if let Some(a) = a {
               if *next_bv >= 1 << MAX_BOUND_VARS {
-                return Err(ElabError::new_e(fsp.span,
-                  format!("too many bound variables (max {})", MAX_BOUND_VARS)))
+                return Err(too_many_bound_vars(&self.env, sp!(ea), Some(a)))
               }
               varmap.insert(a, *next_bv);
               *next_bv *= 2;
@@ -995,6 +1060,7 @@ impl Elaborator {
     };
     let span = x.fspan().unwrap_or_else(|| fsp.clone());
     let x = x.as_atom().ok_or_else(|| ElabError::new_e(span.span, "expected an atom"))?;
+    let x = self.namespaced(x);
     if self.data[x].decl.is_some() {
       return Err(ElabError::new_e(fsp.span,
         format!("duplicate term/def declaration '{}'", self.print(&x))))
@@ -1040,7 +1106,7 @@ impl Elaborator {
     let full = fsp.span;
     let t = Term {atom: x, span, full, vis, args, ret, val};
     self.env.add_term(x, fsp, || t).map_err(|e| e.into_elab_error(full))?;
-    Ok(())
+    self.check_name_policy(full, x)
   }
 
   /// Parse and add a term/def declaration (this is called by the `(add-thm!)` lisp function).
@@ -1049,18 +1115,26 @@ impl Elaborator {
   /// or it may yield if the user provided proof term is a closure that requires evaluation,
   /// in which case it returns `Ok(Err((ap, proof_closure)))` where `(proof_closure)` should
   /// evaluate to some value `proof`, which can be passed to [`finish`] to finish adding
-  /// the theorem to the environment.
+  /// the theorem to the environment. If the optional trailing `:unchecked` flag is given,
+  /// the proof is not checked, in the same way as when `check_proofs` is false.
   ///
   /// [`finish`]: local_context/struct.AwaitingProof.html#method.finish
   pub fn add_thm(&mut self, fsp: FileSpan, es: &[LispVal]) -> Result<StdResult<(), (AwaitingProof, LispVal)>> {
     macro_rules! sp {($e:expr) => {$e.fspan().unwrap_or(fsp.clone()).span}}
-    let (x, bis, hyps, ret, proof) = match es {
-      [x, bis, hyps, ret] => (x, bis, hyps, ret, None),
-      [x, bis, hyps, ret, vis, vtask] => (x, bis, hyps, ret, Some((vis, vtask.clone()))),
-      _ => return Err(ElabError::new_e(fsp.span, "expected 4 or 6 arguments"))
+    let (x, bis, hyps, ret, proof, unchecked) = match es {
+      [x, bis, hyps, ret] => (x, bis, hyps, ret, None, false),
+      [x, bis, hyps, ret, vis, vtask] => (x, bis, hyps, ret, Some((vis, vtask.clone())), false),
+      [x, bis, hyps, ret, vis, vtask, flag] => {
+        if flag.as_atom() != Some(AtomID::UNCHECKED) {
+          return Err(ElabError::new_e(sp!(flag), "expected :unchecked"))
+        }
+        (x, bis, hyps, ret, Some((vis, vtask.clone())), true)
+      }
+      _ => return Err(ElabError::new_e(fsp.span, "expected 4, 6 or 7 arguments"))
     };
     let span = x.fspan().unwrap_or_else(|| fsp.clone());
     let x = x.as_atom().ok_or_else(|| ElabError::new_e(span.span, "expected an atom"))?;
+    let x = self.namespaced(x);
     if self.data[x].decl.is_some() {
       return Err(ElabError::new_e(fsp.span,
         format!("duplicate axiom/theorem declaration '{}'", self.print(&x))))
@@ -1098,7 +1172,7 @@ impl Elaborator {
       if !thm.vis.allowed_visibility(DeclKind::Thm) {
         return Err(ElabError::new_e(sp!(vis), "invalid modifiers for this keyword"))
       }
-      Some(if self.check_proofs {
+      Some(if self.check_proofs && !unchecked {
         let mut de = de.map_proof();
         let var_map = nh.var_map;
         let is = is.into_iter().enumerate().filter_map(|(i, (a, j, ty))| {
@@ -1118,6 +1192,121 @@ impl Elaborator {
     Ok(Ok(()))
   }
 
+  /// Check a proof of a theorem statement without adding anything to the environment
+  /// (this is called by the `(verify-proof)` lisp function). The arguments are
+  /// `[bis, hyps, ret, proof]` or `[bis, ret, proof]`, interpreted as in `add_thm`,
+  /// and the proof is checked the same way, but it must be given directly as a
+  /// `(ds proof)` pair rather than as a closure. On success, returns the sharing
+  /// statistics of the deduplicated proof (see [`Dedup::stats`]).
+  ///
+  /// [`Dedup::stats`]: ../proof/struct.Dedup.html#method.stats
+  pub fn verify_proof(&self, fsp: FileSpan, es: &[LispVal]) -> Result<(usize, usize)> {
+    match es.split_last() {
+      Some((proof, stmt)) if (2..=3).contains(&stmt.len()) =>
+        self.check_prepared(&self.prepare_stmt(fsp, stmt)?, proof),
+      _ => Err(ElabError::new_e(fsp.span, "expected 3 or 4 arguments"))
+    }
+  }
+
+  /// Elaborate a theorem statement, given as `[bis, hyps, ret]` or `[bis, ret]` in the
+  /// format of `add_thm`, so that several proofs can be checked against it using
+  /// [`check_prepared`] without repeating the analysis of the statement
+  /// (this is called by the `(prepare-stmt)` lisp function).
+  ///
+  /// [`check_prepared`]: struct.Elaborator.html#method.check_prepared
+  pub fn prepare_stmt(&self, fsp: FileSpan, es: &[LispVal]) -> Result<PreparedStmt> {
+    macro_rules! sp {($e:expr) => {$e.fspan().unwrap_or(fsp.clone()).span}}
+    let (bis, hyps, ret) = match es {
+      [bis, ret] => (bis, None, ret),
+      [bis, hyps, ret] => (bis, Some(hyps), ret),
+      _ => return Err(ElabError::new_e(fsp.span, "expected 2 or 3 arguments"))
+    };
+    let mut vars = (HashMap::new(), 1);
+    let (lc, args) = self.binders(&fsp, Uncons::from(bis.clone()), &mut vars)?;
+    let mut de = Dedup::new(&args);
+    let nh = NodeHasher::new(&lc, self.format_env(), fsp.clone());
+    let mut is = Vec::new();
+    for e in hyps.into_iter().flat_map(|hyps| Uncons::from(hyps.clone())) {
+      let mut u = Uncons::from(e.clone());
+      if let (Some(ex), Some(ty)) = (u.next(), u.next()) {
+        let x = ex.as_atom().ok_or_else(|| ElabError::new_e(sp!(ex), "expected an atom"))?;
+        let a = if x == AtomID::UNDER {None} else {Some(x)};
+        is.push((a, de.dedup(&nh, &ty)?, ty));
+      } else {
+        return Err(ElabError::new_e(sp!(e), format!("syntax error: {}", self.print(&e))))
+      }
+    }
+    de.dedup(&nh, ret)?;
+    let var_map = nh.var_map;
+    Ok(PreparedStmt {lc, de, var_map, is, fsp})
+  }
+
+  /// Check the proof `proof`, in the format `(ds proof)`, against a statement that was
+  /// elaborated by [`prepare_stmt`]. On success, returns the sharing statistics
+  /// of the deduplicated proof.
+  ///
+  /// [`prepare_stmt`]: struct.Elaborator.html#method.prepare_stmt
+  pub fn check_prepared(&self, stmt: &PreparedStmt, proof: &LispVal) -> Result<(usize, usize)> {
+    let fsp = &stmt.fsp;
+    let mut lc = stmt.lc.clone();
+    let mut de = stmt.de.map_proof();
+    for (i, &(a, j, ref ty)) in stmt.is.iter().enumerate() {
+      if let Some(a) = a {
+        let p = LispVal::atom(a);
+        lc.add_proof(a, ty.clone(), p.clone());
+        de.add(p, ProofHash::Hyp(i, j));
+      }
+    }
+    let mut u = Uncons::from(proof.clone());
+    let (ds, pf) = match (u.next(), u.next(), u.exactly(0)) {
+      (Some(ds), Some(pf), true) => (ds, pf),
+      _ => return Err(ElabError::new_e(proof.fspan().unwrap_or_else(|| fsp.clone()).span,
+        "bad proof format, expected (ds proof)"))
+    };
+    let fe = FormatEnv {source: &self.ast.source, env: &self.env};
+    dummies(fe, fsp, &mut lc, &ds)?;
+    let nh = NodeHasher {var_map: stmt.var_map.clone(), lc: &lc, fe, fsp: fsp.clone()};
+    de.dedup(&nh, &pf)?;
+    Ok(de.stats())
+  }
+
+  /// Get the list of bound variables in the local context that the expression `e`
+  /// depends on (this is called by the `(expr-deps)` lisp function). This uses the
+  /// same analysis as the declaration of a definition, so regular variables contribute
+  /// their declared dependencies and variables bound by a term constructor are removed.
+  pub fn expr_deps(&self, sp: Span, e: &LispVal) -> Result<LispVal> {
+    let mut ba = BuildArgs::default();
+    let mut bvs = vec![];
+    let mut push_bound = |ba: &mut BuildArgs, a: Option<AtomID>| -> Result<()> {
+      ba.push_bound(a).ok_or_else(|| too_many_bound_vars(&self.env, sp, a))?;
+      bvs.push(LispVal::atom(a.unwrap_or(AtomID::UNDER)));
+      Ok(())
+    };
+    for &(_, a, ref is) in &self.lc.var_order {
+      let is = match (is, a) {
+        (Some(is), _) => is,
+        (None, Some(a)) => &self.lc.vars[&a].1,
+        (None, None) => continue,
+      };
+      match is {
+        InferSort::Bound(_) => push_bound(&mut ba, a)?,
+        InferSort::Reg(_, deps) => if let Some(a) = a {
+          let n = deps.iter().fold(0, |n, b| n | ba.map.get(b).copied().unwrap_or(0));
+          ba.map.insert(a, n);
+        },
+        InferSort::Unknown {..} => if let Some(a) = a { ba.map.insert(a, 0); },
+      }
+    }
+    let mut ds = self.lc.vars.iter()
+      .filter(|(_, is)| matches!(is, (true, InferSort::Bound(_))))
+      .map(|(&a, _)| a).collect::<Vec<_>>();
+    ds.sort();
+    for a in ds { push_bound(&mut ba, Some(a))? }
+    let n = ba.try_expr_deps(&self.env, e).ok_or_else(|| ElabError::new_e(sp,
+      format!("expected a fully elaborated expression, got {}", self.print(e))))?;
+    Ok(Environment::deps(&bvs, n))
+  }
+
   fn finish_add_thm(&mut self, fsp: FileSpan, mut t: Thm, res: Option<Option<ThmVal>>) -> Result<()> {
     macro_rules! sp {($e:expr) => {$e.fspan().unwrap_or(fsp.clone()).span}}
     t.proof = res.map(|res| res.and_then(|ThmVal {mut de, var_map, mut lc, is: is2, proof: e}| {
@@ -1142,8 +1331,43 @@ impl Elaborator {
         None
       })
     }));
-    let sp = fsp.span;
-    self.env.add_thm(t.atom, fsp, || t).map_err(|e| e.into_elab_error(sp))?;
-    Ok(())
+    let (sp, atom) = (fsp.span, t.atom);
+    self.env.add_thm(atom, fsp, || t).map_err(|e| e.into_elab_error(sp))?;
+    self.check_name_policy(sp, atom)
+  }
+}
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, Instant};
+  use super::*;
+
+  /// Simulate a long proof in which every refine step creates two metavariables and
+  /// assigns one of them, and compare cleaning up the metavariable list after every step
+  /// with `maybe_clean_mvars`. This is a benchmark, not a test; run it with
+  /// `cargo test --release bench_clean_mvars -- --ignored --nocapture`.
+  /// For reference, a standalone copy of this loop (using `Rc<RefCell<_>>` in place of
+  /// `LispVal`) in a release build took 92ms vs 1.1ms at 10000 steps, 1.3s vs 4.2ms at
+  /// 30000 steps, and 15s vs 13ms at 100000 steps.
+  #[test] #[ignore]
+  fn bench_clean_mvars() {
+    fn run(steps: usize, clean: fn(&mut LocalContext)) -> (Duration, usize) {
+      let mut lc = LocalContext::new();
+      let start = Instant::now();
+      for _ in 0..steps {
+        let e = lc.new_mvar(InferTarget::Unknown, None);
+        lc.new_mvar(InferTarget::Unknown, None);
+        e.as_ref_(|r| *r = LispVal::atom(AtomID::UNDER));
+        lc.mvars_assigned += 1;
+        clean(&mut lc);
+      }
+      lc.clean_mvars();
+      (start.elapsed(), lc.mvars.len())
+    }
+    for &steps in &[1000, 10000, 30000] {
+      let (t1, n1) = run(steps, LocalContext::clean_mvars);
+      let (t2, n2) = run(steps, LocalContext::maybe_clean_mvars);
+      assert_eq!(n1, n2);
+      println!("{} steps: clean_mvars {:?}, maybe_clean_mvars {:?}", steps, t1, t2);
+    }
   }
-}
\ No newline at end of file
+}
@@ -8,6 +8,7 @@ use std::hash::Hash;
 use std::ops::Index;
 use std::result::Result as StdResult;
 use std::mem;
+use std::fmt;
 use std::collections::{HashMap, hash_map::Entry};
 use super::environment::{AtomID, Type};
 use super::{LocalContext, ElabError, Result, Environment,
@@ -97,7 +98,8 @@ pub struct Dedup<H: NodeHash> {
   /// a reference to the object in the map to ensure that it is not deallocated.
   /// (There is no safety problem here, but if the object gets deallocated and
   /// another takes its place, we can get a false positive hit in the map.)
-  prev: HashMap<*const LispKind, (LispVal, usize)>,
+  /// Since this map is purely keyed on pointer identity, we use a fast pointer hash.
+  prev: PtrHashMap<*const LispKind, (LispVal, usize)>,
   /// The list of allocated objects. At each index, we store `(hash, shared, deps)` where
   /// `hash` is the hash object, `shared` is true if this object has more than one
   /// reference, and `deps` is the dependencies of this expression
@@ -123,12 +125,19 @@ impl<H: NodeHash> Dedup<H> {
       })).collect();
     Dedup {
       map: vec.iter().enumerate().map(|(i, r)| (r.0.clone(), i)).collect(),
-      prev: HashMap::new(),
+      prev: PtrHashMap::default(),
       vec,
       bv,
     }
   }
 
+  /// Get sharing statistics for the objects allocated so far, as a pair
+  /// `(total, shared)` where `total` is the number of allocated objects and `shared`
+  /// is the number of them that are referenced more than once.
+  pub fn stats(&self) -> (usize, usize) {
+    (self.vec.len(), self.vec.iter().filter(|e| e.1).count())
+  }
+
   /// Insert a new hash object `v`, originating from lisp object `p`,
   /// into the `Dedup`, returning the allocated index.
   pub fn add(&mut self, p: LispVal, v: H) -> usize {
@@ -551,6 +560,27 @@ impl ProofHash {
   }
 }
 
+/// Get the list of pairs `(i, j)` of arguments to a theorem with binders `args` that
+/// are required to be disjoint: either `j` is a bound variable and `i < j`, or `j` is a
+/// regular variable which does not depend on the bound variable `i`.
+pub fn dv_pairs(args: &[(Option<AtomID>, Type)]) -> Vec<(usize, usize)> {
+  let mut dvs = vec![];
+  let mut bvs = vec![];
+  for (i, (_, t)) in args.iter().enumerate() {
+    match t {
+      Type::Bound(_) => {
+        bvs.push(i);
+        dvs.extend((0..i).map(|j| (j, i)));
+      }
+      &Type::Reg(_, mut d) =>
+        dvs.extend(bvs.iter()
+          .filter(|_| { let old = d; d /= 2; old & 1 == 0 })
+          .map(|&j| (j, i)))
+    }
+  }
+  dvs
+}
+
 impl NodeHash for ProofHash {
   const REF: fn(usize) -> Self = Self::Ref;
 
@@ -608,23 +638,9 @@ impl NodeHash for ProofHash {
                 }),
               };
               if !ok {
-                let mut dvs = vec![];
-                let mut bvs = vec![];
-                for (i, (_, t)) in td.args.iter().enumerate() {
-                  match t {
-                    Type::Bound(_) => {
-                      bvs.push(i);
-                      dvs.extend((0..i).map(|j| (j, i)));
-                    }
-                    &Type::Reg(_, mut d) =>
-                      dvs.extend(bvs.iter()
-                        .filter(|_| { let old = d; d /= 2; old & 1 == 0 })
-                        .map(|&j| (j, i)))
-                  }
-                }
                 let mut err = format!("disjoint variable violation at {}", adata.name);
                 let args: Vec<_> = Uncons::from(r.clone()).skip(1).collect();
-                for (i, j) in dvs {
+                for (i, j) in dv_pairs(&td.args) {
                   if de.vec[ns[i]].2 & de.vec[ns[j]].2 != 0 {
                     use std::fmt::Write;
                     write!(err, "\n  ({}, {}) -> ({}, {})",
@@ -657,6 +673,31 @@ impl NodeHash for ProofHash {
               }
               _ => return Err(nh.err_sp(fsp, format!("incorrect :sym format {}", nh.fe.to(r))))
             },
+            AtomID::REFL => match u.next() {
+              Some(e) if u.exactly(0) => {
+                let e = de.dedup(nh, &e)?;
+                if Self::is_conv(de, e) {
+                  return Err(nh.err_sp(fsp, format!("incorrect :refl format {}", nh.fe.to(r))))
+                }
+                ProofHash::Refl(e)
+              }
+              _ => return Err(nh.err_sp(fsp, format!("incorrect :refl format {}", nh.fe.to(r))))
+            },
+            AtomID::CONG => {
+              let ty = u.next().ok_or_else(||
+                nh.err_sp(fsp, format!("incorrect :cong format {}", nh.fe.to(r))))?;
+              let tid = ty.as_atom().and_then(|a| nh.fe.term(a))
+                .ok_or_else(|| nh.err(&ty, "expected a term"))?;
+              let mut ns = Vec::new();
+              for e in u {
+                let c = de.dedup(nh, &e)?;
+                ns.push(Self::as_conv(de, c))
+              }
+              if ns.len() != nh.fe.terms[tid].args.len() {
+                return Err(nh.err_sp(fsp, format!("incorrect :cong format {}", nh.fe.to(r))))
+              }
+              ProofHash::Cong(tid, ns.into())
+            }
             AtomID::UNFOLD => {
               let (ty, es, prf) = match (u.next(), u.next(), u.next(), u.next()) {
                 (Some(ty), Some(es), Some(prf), None) if u.exactly(0) => (ty, es, prf),
@@ -743,6 +784,44 @@ impl Node for ProofNode {
   }
 }
 
+/// The error produced by [`Subst`] when a heap element refers to itself, directly or
+/// through other heap elements. This cannot happen for heaps produced by the elaborator,
+/// but it can happen for corrupt imported data, or if an argument of the substitution
+/// is `#undef` (since the argument slots of the heap refer to themselves).
+///
+/// [`Subst`]: struct.Subst.html
+#[derive(Copy, Clone, Debug)]
+pub struct CyclicHeap(pub usize);
+
+impl fmt::Display for CyclicHeap {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "cyclic reference to heap element {} during substitution", self.0)
+  }
+}
+
+impl From<CyclicHeap> for String {
+  fn from(e: CyclicHeap) -> String { e.to_string() }
+}
+
+impl CyclicHeap {
+  /// Convert this error into an `ElabError` at the given location.
+  pub fn into_elab_error(self, sp: Span) -> ElabError { ElabError::new_e(sp, self.to_string()) }
+}
+
+/// The state of a heap element in a [`Subst`].
+///
+/// [`Subst`]: struct.Subst.html
+#[derive(Debug)]
+enum SubstState {
+  /// The substitution for this element has not been computed yet.
+  Todo,
+  /// The substitution for this element is currently being computed. Encountering
+  /// a reference to an element in this state indicates a cycle.
+  Active,
+  /// The computed substitution for this element.
+  Done(LispVal),
+}
+
 /// A structure for performing substitutions on expressions represented as lisp values.
 #[derive(Debug)]
 pub struct Subst<'a> {
@@ -750,56 +829,78 @@ pub struct Subst<'a> {
   env: &'a Environment,
   /// The heap (from the theorem statement).
   heap: &'a [ExprNode],
-  /// The already computed substitutions for elements of the heap, with unknown
-  /// values set to `#undef`.
-  subst: Vec<LispVal>,
+  /// The already computed substitutions for elements of the heap.
+  subst: Vec<SubstState>,
 }
 
 impl<'a> Subst<'a> {
   /// Contruct a new `Subst` object. `args` should be initialized to
   /// the arguments to the theorem application (possibly metavariables).
-  pub fn new(env: &'a Environment, heap: &'a [ExprNode], mut args: Vec<LispVal>) -> Subst<'a> {
-    args.resize(heap.len(), LispVal::undef());
-    Subst {env, heap, subst: args}
+  pub fn new(env: &'a Environment, heap: &'a [ExprNode], args: Vec<LispVal>) -> Subst<'a> {
+    let mut subst: Vec<_> = args.into_iter()
+      .map(|e| if e.is_def() {SubstState::Done(e)} else {SubstState::Todo}).collect();
+    subst.resize_with(heap.len(), || SubstState::Todo);
+    Subst {env, heap, subst}
+  }
+
+  /// Look up heap element `i`, computing it using `f` if it is not already known.
+  fn get(&mut self, i: usize,
+    f: impl FnOnce(&mut Self, &'a ExprNode) -> StdResult<LispVal, CyclicHeap>
+  ) -> StdResult<LispVal, CyclicHeap> {
+    match mem::replace(&mut self.subst[i], SubstState::Active) {
+      SubstState::Done(e) => {self.subst[i] = SubstState::Done(e.clone()); Ok(e)}
+      SubstState::Active => Err(CyclicHeap(i)),
+      SubstState::Todo => {
+        let heap = self.heap;
+        let e = f(self, &heap[i])?;
+        self.subst[i] = SubstState::Done(e.clone());
+        Ok(e)
+      }
+    }
   }
 
   /// Substitute in an `ExprNode`. This version does not support dummy variables,
   /// which means it can be used for theorem applications but not definition unfolding.
-  pub fn subst(&mut self, e: &ExprNode) -> LispVal {
+  pub fn subst(&mut self, e: &ExprNode) -> StdResult<LispVal, CyclicHeap> {
     match *e {
-      ExprNode::Ref(i) => {
-        let e = &self.subst[i];
-        if e.is_def() {return e.clone()}
-        let e = self.subst(&self.heap[i]);
-        self.subst[i] = e.clone();
-        e
-      }
+      ExprNode::Ref(i) => self.get(i, Self::subst),
       ExprNode::Dummy(_, _) => unreachable!(),
       ExprNode::App(t, ref es) => {
         let mut args = vec![LispVal::atom(self.env.terms[t].atom)];
-        args.extend(es.iter().map(|e| self.subst(e)));
-        LispVal::list(args)
+        for e in es {args.push(self.subst(e)?)}
+        Ok(LispVal::list(args))
       }
     }
   }
 
   /// Substitute in an `ExprNode`. This version creates new metavariables
   /// when encountering `Dummy` nodes.
-  pub fn subst_mut(&mut self, lc: &mut LocalContext, e: &ExprNode) -> LispVal {
+  pub fn subst_mut(&mut self, lc: &mut LocalContext, e: &ExprNode) -> StdResult<LispVal, CyclicHeap> {
     match *e {
-      ExprNode::Ref(i) => {
-        let e = &self.subst[i];
-        if e.is_def() {return e.clone()}
-        let e = self.subst_mut(lc, &self.heap[i]);
-        self.subst[i] = e.clone();
-        e
-      }
-      ExprNode::Dummy(_, s) => lc.new_mvar(InferTarget::Bound(self.env.sorts[s].atom), None),
+      ExprNode::Ref(i) => self.get(i, |this, e| this.subst_mut(lc, e)),
+      ExprNode::Dummy(_, s) => Ok(lc.new_mvar(InferTarget::Bound(self.env.sorts[s].atom), None)),
       ExprNode::App(t, ref es) => {
         let mut args = vec![LispVal::atom(self.env.terms[t].atom)];
-        args.extend(es.iter().map(|e| self.subst_mut(lc, e)));
-        LispVal::list(args)
+        for e in es {args.push(self.subst_mut(lc, e)?)}
+        Ok(LispVal::list(args))
       }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn subst_cyclic_heap() {
+    let env = Environment::new();
+    let heap = [ExprNode::Ref(1), ExprNode::Ref(0)];
+    match Subst::new(&env, &heap, vec![]).subst(&ExprNode::Ref(0)) {
+      Err(CyclicHeap(i)) => assert_eq!(i, 0),
+      Ok(e) => panic!("expected a cyclic heap error, got {:?}", e),
+    }
+    let mut lc = LocalContext::new();
+    assert!(Subst::new(&env, &heap, vec![]).subst_mut(&mut lc, &ExprNode::Ref(1)).is_err());
+  }
+}
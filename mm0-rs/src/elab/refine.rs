@@ -454,6 +454,7 @@ impl Elaborator {
             let mut args = Vec::with_capacity(num_args);
             if !u.extend_into(num_args, &mut args) {return Err(err!(e, "not enough arguments"))}
             Subst::new(&self.env, &tdata.heap, args).subst(&tdata.ret)
+              .map_err(|r| err!(e, r.to_string()))?
           }
         }
       }
@@ -518,10 +519,41 @@ impl Elaborator {
         if let Some(sp) = m.get().fspan() {e = e.span(sp)}
       }
       *m.get_mut() = e;
+      self.lc.mvars_assigned += 1;
       Ok(())
     }
   }
 
+  /// Assign metavariable `mv` to `e` (or fail), after checking that the sort of `e`
+  /// matches the target of `mv`. This is used by the `assign-mvar` builtin.
+  pub(crate) fn assign_mvar(&mut self, sp: Span, mv: &LispVal, e: &LispVal) -> Result<()> {
+    let tgt = mv.mvar_target().ok_or_else(|| ElabError::new_e(sp, "expected a metavariable"))?;
+    let s = match e.mvar_target() {
+      Some(tgt2) => tgt2.sort().and_then(|a| self.data[a].sort),
+      None => Some(self.infer_sort(sp, e)?),
+    };
+    if let Some(s) = s {
+      match tgt {
+        InferTarget::Unknown => {}
+        InferTarget::Provable => if !self.sorts[s].mods.contains(Modifiers::PROVABLE) {
+          return Err(ElabError::new_e(sp, format!("type error: expected provable, got {}", self.print(&s))))
+        },
+        InferTarget::Bound(a) | InferTarget::Reg(a) => if self.data[a].sort != Some(s) {
+          return Err(ElabError::new_e(sp,
+            format!("type error: expected {}, got {}", self.print(&a), self.print(&s))))
+        },
+      }
+    }
+    match mv.as_mvar(|mv, m| self.assign(false, mv, m, e)) {
+      None => Err(ElabError::new_e(sp, "expected a metavariable")),
+      Some(Ok(())) => {self.lc.clean_mvars(); Ok(())}
+      Some(Err(AssignError::Cyclic)) =>
+        Err(ElabError::new_e(sp, "occurs-check failed, can't build infinite assignment")),
+      Some(Err(AssignError::BoundVar)) =>
+        Err(ElabError::new_e(sp, format!("type error: expected bound var, got {}", self.print(e)))),
+    }
+  }
+
   /// Unify expressions `e1` and `e2`. Returns a conversion proof
   /// `u: e1 = e2`, with `#undef` meaning that `e1` and `e2` are equal after unification.
   fn unify(&mut self, sp: Span, e1: &LispVal, e2: &LispVal) -> Result<LispVal> {
@@ -628,7 +660,7 @@ impl Elaborator {
         return Err(format!("bad term: {}", self.print(&u1)))
       }
       let e1_unfolded = Subst::new(&self.env, &val.heap, args.clone())
-        .subst_mut(&mut self.lc, &val.head);
+        .subst_mut(&mut self.lc, &val.head)?;
       let conv = self.unify1(&e1_unfolded, e2)?;
       let conv = LispVal::unfold(a, args, if conv.is_def() {conv} else {e1_unfolded});
       Ok(if sym {LispVal::sym(conv)} else {conv})
@@ -693,11 +725,24 @@ impl Elaborator {
             }
           }
           RefineExpr::App {sp, sp2, im, head: a, u} => {
-            let head = LispVal::atom(a).span(self.fspan(sp2));
             if let Some((_, ty, _)) = self.lc.get_proof(a) {
+              let head = LispVal::atom(a).span(self.fspan(sp2));
               self.spans.insert_if(sp2, || ObjectKind::proof(head.clone()));
               RState::RefineArgs {sp, ty: ty.clone(), tgt, p: head, u}
-            } else if let Some(DeclKey::Thm(t)) = self.data[a].decl {
+            } else if let Some((a, t)) = match self.data[a].decl {
+              Some(DeclKey::Thm(t)) => Some((a, t)),
+              Some(_) => None,
+              None => self.resolve_open(a, |b, ad|
+                if let Some(DeclKey::Thm(t)) = ad.decl {Some((b, t))} else {None}),
+            } {
+              let head = LispVal::atom(a).span(self.fspan(sp2));
+              if let Some(msg) = self.deprecated.get(&t) {
+                let msg = match msg {
+                  None => format!("theorem '{}' is deprecated", self.data[a].name),
+                  Some(msg) => format!("theorem '{}' is deprecated: {}", self.data[a].name, msg),
+                };
+                self.report(ElabError::warn(sp2, msg))
+              }
               RState::RefineBis {sp, sp2, tgt, im, t, args: vec![head], u}
             } else {
               return Err(ElabError::new_e(sp2, format!(
@@ -791,8 +836,9 @@ impl Elaborator {
               }
             }
             let mut subst = Subst::new(&self.env, &tdata.heap, Vec::from(&args[1..]));
-            let hyps = tdata.hyps.iter().map(|(_, h)| subst.subst(h)).collect::<Vec<_>>();
-            let ret = subst.subst(&tdata.ret);
+            let hyps = tdata.hyps.iter().map(|(_, h)| subst.subst(h))
+              .collect::<StdResult<Vec<_>, _>>().map_err(|r| r.into_elab_error(sp))?;
+            let ret = subst.subst(&tdata.ret).map_err(|r| r.into_elab_error(sp))?;
             break RState::RefineHyps {
               res: if u.len() <= hyps.len() {
                 RefineHypsResult::Ok(self.unify(sp, &tgt, &ret)?)
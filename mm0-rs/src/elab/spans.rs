@@ -152,6 +152,11 @@ impl<T> Spans<T> {
       v.iter_mut().find(|x| x.0 == sp).map(|x| &mut x.1))
   }
 
+  /// Remove all data elements that do not satisfy the predicate `f`.
+  pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+    for v in self.data.values_mut() { v.retain(|x| f(&x.1)) }
+  }
+
   /// Returns an iterator over all data elements in spans that overlap the target
   /// position. (Spans are considered as closed,
   /// i.e. `start <= pos <= end`, for this purpose.)
@@ -0,0 +1,543 @@
+//! Tests for the elaborator and the lisp builtins, run by elaborating small MM1 files.
+//!
+//! Most tests check their results from inside the MM1 file itself, by calling
+//! `(error)` when something is wrong, so that the test only needs to assert that
+//! elaboration produced no errors.
+
+use std::sync::{Arc, atomic::AtomicBool};
+use std::result::Result as StdResult;
+use futures::channel::oneshot::Receiver;
+use crate::util::{BoxError, FileRef};
+use crate::lined_string::LinedString;
+use super::{elaborate, ElabError, ErrorLevel, FrozenEnv};
+
+/// A small propositional logic with an equivalence relation `<->`,
+/// which is enough for testing most of the tactics.
+const PRELUDE: &str = "\
+delimiter $ ( ) ~ $;
+strict provable sort wff;
+term im (ph ps: wff): wff; infixr im: $->$ prec 25;
+term not (ph: wff): wff; prefix not: $~$ prec 40;
+term iff (ph ps: wff): wff; infixl iff: $<->$ prec 20;
+axiom ax_1 (ph ps: wff): $ ph -> ps -> ph $;
+axiom ax_mp (ph ps: wff): $ ph -> ps $ > $ ph $ > $ ps $;
+axiom iff_refl (ph: wff): $ ph <-> ph $;
+axiom iff_symm (ph ps: wff): $ ph <-> ps $ > $ ps <-> ph $;
+axiom iff_trans (ph ps ch: wff): $ ph <-> ps $ > $ ps <-> ch $ > $ ph <-> ch $;
+";
+
+/// Elaborate the MM1 file `src`, returning the errors and the final environment.
+fn elab(src: &str) -> (Vec<ElabError>, FrozenEnv) {
+  let text = Arc::new(LinedString::from(src.to_owned()));
+  let (_, ast) = crate::parser::parse(text, None);
+  assert!(ast.errors.is_empty(), "parse errors");
+  let path: FileRef = std::env::current_dir().unwrap().join("test.mm1").into();
+  let (_, errs, env) = futures::executor::block_on(elaborate(
+    Arc::new(ast), path, false, Arc::new(AtomicBool::new(false)), None,
+    |_| -> StdResult<Receiver<((), FrozenEnv)>, BoxError> {Err("imports are not supported".into())}));
+  (errs, env)
+}
+
+/// Elaborate `PRELUDE` followed by `src`, returning the messages at level `level`.
+fn messages(src: &str, level: ErrorLevel) -> Vec<String> {
+  let (errs, _) = elab(&format!("{}{}", PRELUDE, src));
+  errs.into_iter().filter(|e| e.level == level).map(|e| e.kind.msg()).collect()
+}
+
+/// Elaborate `PRELUDE` followed by `src`, and panic if there are any errors.
+fn check(src: &str) {
+  let errs = messages(src, ErrorLevel::Error);
+  assert!(errs.is_empty(), "elaboration failed:\n{}", errs.join("\n"))
+}
+
+#[test]
+fn prelude() { check("") }
+
+#[test]
+fn try_restores_state() {
+  check("
+theorem try_test (ph: wff): $ ph -> ph -> ph $ = (focus
+  (def gs (get-goals))
+  (if (def? (try (fn () (set-goals) (error \"fail\")))) (error \"try returned a value\"))
+  (if (null? (get-goals)) (error \"try did not restore the goals\"))
+  (if {(get-goals) == gs} #undef (error \"try changed the goals\"))
+  (if {(try (fn () 1)) = 1} #undef (error \"try lost the return value\"))
+  '(ax_1 ph ph));")
+}
+
+#[test]
+fn env_snapshot_rollback() {
+  check("
+do {
+  (def (add-snap-term)
+    (add-term! 'snap_term '([_ wff ()]) 'wff)
+    (if (def? (declared? 'snap_term)) #undef (error \"snap_term was not added\")))
+  (with-env-snapshot (fn () (add-snap-term) 'rollback))
+  (if (def? (declared? 'snap_term)) (error \"snap_term survived the rollback\"))
+  (try (fn () (with-env-snapshot (fn () (add-snap-term) (error \"fail\")))))
+  (if (def? (declared? 'snap_term)) (error \"snap_term survived the error\"))
+  (with-env-snapshot add-snap-term)
+  (if (def? (declared? 'snap_term)) #undef (error \"snap_term was rolled back\"))
+};")
+}
+
+#[test]
+fn ratio_arithmetic() {
+  check("
+do {
+  (def half (ratio 1 2))
+  (if (number? half) #undef (error \"a ratio is not a number\"))
+  (if {half < 1} #undef (error \"1/2 < 1 failed\"))
+  (if (< 0 half 1) #undef (error \"0 < 1/2 < 1 failed\"))
+  (if {half >= 1} (error \"1/2 >= 1\"))
+  (if {(+ half half) = 1} #undef (error \"1/2 + 1/2 = 1 failed\"))
+  (if {(max half 1) = 1} #undef (error \"max failed\"))
+  (if {(min half 1) = half} #undef (error \"min failed\"))
+  (if {(abs (- half)) = half} #undef (error \"abs failed\"))
+  (if {(sign (- half)) = (- 1)} #undef (error \"sign failed\"))
+  (if {{(ratio 7 2) % 2} = (ratio 3 2)} #undef (error \"% failed\"))
+};")
+}
+
+/// Time repeated `(get-decl x #t)` calls over many theorems, which materialize the
+/// proofs and go through the proof cache. This is a benchmark, not a test; run it with
+/// `cargo test --release bench_get_decl_proofs -- --ignored --nocapture`.
+#[test] #[ignore]
+fn bench_get_decl_proofs() {
+  use std::fmt::Write;
+  use std::time::Instant;
+  let mut src = String::new();
+  for i in 0..500 {
+    writeln!(src, "theorem bench{} (ph ps: wff): $ ps -> ph -> ps -> ph $ = \
+      '(ax_mp (ax_1 $ ph -> ps -> ph $ ps) (ax_1 ph ps));", i).unwrap();
+  }
+  src.push_str("do {\n  (def thms '(");
+  for i in 0..500 { write!(src, " bench{}", i).unwrap() }
+  src.push_str("))\n  (def (rep n f) (if {n > 0} (begin (f) (rep {n - 1} f))))\n};\n");
+  let base = {
+    let start = Instant::now();
+    check(&src);
+    start.elapsed()
+  };
+  for &n in &[1, 10, 100] {
+    let start = Instant::now();
+    check(&format!("{}do {{ (rep {} (fn () (map (fn (t) (get-decl t #t)) thms))) }};", src, n));
+    println!("{} x 500 proofs: {:?}", n, start.elapsed().checked_sub(base).unwrap_or_default());
+  }
+}
+
+#[test]
+fn report_at_related_with_stack_trace() {
+  let (errs, _) = elab(&format!("{}{}", PRELUDE, "
+do {
+  (report-at 'warn (copy-span 'foo #t) \"msg\" (list (list 'foo \"related\")))
+};"));
+  let err = errs.iter().find(|e| e.level == ErrorLevel::Warning).expect("no warning reported");
+  match &err.kind {
+    super::ElabErrorKind::Boxed(_, Some(info)) =>
+      assert!(info.iter().any(|(_, msg)| msg.to_string() == "related")),
+    _ => panic!("related information was dropped"),
+  }
+}
+
+#[test]
+fn prepared_handles_not_reused() {
+  check("
+do {
+  (def bis '([ph wff ()] [ps wff ()]))
+  (def pf '(() (ax_1 ph ps)))
+  (def stale (ref! #undef))
+  (with-env-snapshot (fn ()
+    (set! stale (prepare-stmt bis () $ ph -> ps -> ph $))
+    'rollback))
+  (def h (prepare-stmt bis () $ ph -> ps -> ph $))
+  (if {(get! stale) = h} (error \"a rolled back handle was reused\"))
+  (if (def? (try (fn () (check-prepared (get! stale) pf))))
+    (error \"a rolled back handle was accepted\"))
+  (if (check-prepared h pf) #undef (error \"check-prepared failed\"))
+};")
+}
+
+#[test]
+fn symm_swaps_goal() {
+  check("
+theorem symm_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (symm)
+  (if {(goal-type (hd (get-goals))) == $ ph <-> ps $} #undef (error \"symm did not swap the goal\"))
+  (symm)
+  (if {(goal-type (hd (get-goals))) == $ ps <-> ph $} #undef (error \"symm twice is not the identity\"))
+  (symm)
+  'h);")
+}
+
+#[test]
+fn trans_splits_goal() {
+  check("
+theorem trans_test (ph ps ch: wff) (h1: $ ph <-> ps $) (h2: $ ps <-> ch $): $ ph <-> ch $ = (focus
+  (trans $ ps $)
+  (if {(map goal-type (get-goals)) == (list $ ph <-> ps $ $ ps <-> ch $)} #undef
+    (error \"trans produced the wrong goals\"))
+  'h1 'h2);")
+}
+
+#[test]
+fn expr_diff_path() {
+  check("
+do {
+  (if {(expr-diff '(im a (im b c)) '(im a (im d c))) == '((2 1) b d)} #undef
+    (error \"expr-diff returned the wrong path\"))
+  (if (def? (expr-diff '(im a (im b c)) '(im a (im b c))))
+    (error \"expr-diff found a difference in equal expressions\"))
+};")
+}
+
+#[test]
+fn expr_diff_pretty_marks() {
+  check("
+do {
+  (if {(expr-diff-pretty '(im a (im b c)) '(im a (im d c))) ==
+       \"(im a (im «b» c))\\n(im a (im «d» c))\"} #undef
+    (error \"expr-diff-pretty marked the wrong subterm\"))
+};")
+}
+
+#[test]
+fn record_and_replay() {
+  check("
+do { (def saved (ref!)) };
+theorem record_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (start-recording)
+  (symm)
+  (if (def? (try (fn () (refine '(iff_refl ph)) 'ok))) (error \"iff_refl should not unify\"))
+  (refine 'h)
+  (set! saved (stop-recording))
+  (if {(get! saved) == '((symm) (refine h))} #undef (error \"the wrong calls were recorded\")));
+theorem replay_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (replay (get! saved)));
+theorem replay_last_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (replay));")
+}
+
+#[test]
+fn proof_script_reproduces_proof() {
+  const STMT: &str = "(ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $";
+  let script = messages(&format!("
+theorem record_test {} = (focus
+  (start-recording)
+  (symm)
+  (refine 'h)
+  (stop-recording)
+  (display (->string (proof-script))));", STMT), ErrorLevel::Info);
+  assert_eq!(script.len(), 1, "expected one script, got {:?}", script);
+  check(&format!("theorem script_test {} = {};", STMT, script[0]))
+}
+
+#[test]
+fn provable_shallow_search() {
+  check("
+theorem provable_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (if (provable? 'iff_symm) #undef (error \"the goal is provable by iff_symm\"))
+  (if (provable? 'ax_1) (error \"the goal is not provable by ax_1\"))
+  '(iff_symm h));")
+}
+
+#[test]
+fn step_budget() {
+  check("
+do {
+  (def (spin n) (spin {n + 1}))
+  (if (def? (try (fn () (with-step-budget 100 (fn () (spin 0))))))
+    (error \"the budget did not stop the loop\"))
+  (if {(with-step-budget 100 (fn () 42)) = 42} #undef (error \"the budget stopped a short thunk\"))
+};")
+}
+
+#[test]
+fn copy_span_deep() {
+  check("
+do {
+  (def sp (make-span (span-file 'anchor) 1 2))
+  (def e (copy-span-deep sp '(f (g x))))
+  (if {(map span-start (list e (nth 1 e) (nth 1 (nth 1 e)))) == '(1 1 1)} #undef
+    (error \"copy-span-deep missed a subterm\"))
+};")
+}
+
+#[test]
+fn seeded_random() {
+  check("
+do {
+  (def (draw seed) (set-seed seed) (list (random 1000000) (random 1000000) (random 1000000)))
+  (if {(draw 1) == (draw 1)} #undef (error \"the same seed gave different numbers\"))
+  (if {(draw 1) == (draw 2)} (error \"different seeds gave the same numbers\"))
+  (def k (random 10))
+  (if (and {k >= 0} {k < 10}) #undef (error \"random is out of range\"))
+};")
+}
+
+#[test]
+fn seeded_choose() {
+  check("
+do {
+  (def xs '(a b c d e f g h))
+  (def (draw seed) (set-seed seed) (list (choose xs) (choose xs) (choose xs) (choose xs)))
+  (if {(draw 3) == (draw 3)} #undef (error \"the same seed chose differently\"))
+  (if (def? (try (fn () (choose ())))) (error \"choose accepted an empty list\"))
+};")
+}
+
+#[test]
+fn goals_closed_by() {
+  check("
+theorem closed_test (ph ps ch: wff) (h1: $ ph <-> ps $) (h2: $ ps <-> ch $): $ ph <-> ch $ = (focus
+  (trans $ ps $)
+  (def gs (get-goals))
+  (def closed (goals-closed-by (fn () (refine 'h1))))
+  (if (and (not (null? closed)) (null? (tl closed)) (ref-eq? (hd closed) (hd gs))) #undef
+    (error \"goals-closed-by should return exactly the first goal\"))
+  'h2);")
+}
+
+#[test]
+fn made_progress() {
+  check("
+theorem progress_test (ph ps: wff) (h: $ ph <-> ps $): $ ps <-> ph $ = (focus
+  (if (made-progress? (fn () #undef)) (error \"a no-op tactic made progress\"))
+  (if (made-progress? (fn () (symm))) #undef (error \"symm made no progress\"))
+  'h);")
+}
+
+#[test]
+fn now_requires_solved_goal() {
+  check("
+theorem now_test (ph ps ch: wff) (h1: $ ph <-> ps $) (h2: $ ps <-> ch $): $ ph <-> ch $ = (focus
+  (if (def? (try (fn () (now (fn () (trans $ ps $))) 'solved)))
+    (error \"now accepted a partial proof\"))
+  (if (goal? (hd (get-goals))) #undef (error \"the failed now was not rolled back\"))
+  (now (fn () (trans $ ps $) (refine 'h1 'h2))));
+theorem now_assigned (ph: wff): $ ph <-> ph $ = (focus
+  (now (fn () (set! (hd (get-goals)) '(iff_refl ph))))
+  (if (def? (try (fn () (now (fn () #undef)) 'ok))) (error \"now ran with no goals\")));")
+}
+
+#[test]
+fn solve_rolls_back() {
+  check("
+theorem solve_test (ph ps ch: wff) (h1: $ ph <-> ps $) (h2: $ ps <-> ch $): $ ph <-> ch $ = (focus
+  (trans $ ps $)
+  (if (solve (fn () (refine 'h1))) (error \"solve succeeded with a goal left\"))
+  (if (and (goal? (hd (get-goals))) (goal? (nth 1 (get-goals)))) #undef
+    (error \"solve did not roll back the proof state\"))
+  (if (solve (fn () (refine 'h1 'h2))) #undef (error \"solve failed to close both goals\")));")
+}
+
+#[test]
+fn deprecated_theorem_warns_per_use() {
+  let warns = messages("
+do { (deprecate 'ax_1 \"use something else\") };
+theorem dep_once (ph ps: wff): $ ph -> ps -> ph $ = '(ax_1 ph ps);
+theorem dep_twice (ph ps: wff): $ ps -> ph -> ps -> ph $ =
+  '(ax_mp (ax_1 $ ph -> ps -> ph $ ps) (ax_1 ph ps));", ErrorLevel::Warning);
+  assert_eq!(warns, vec!["theorem 'ax_1' is deprecated: use something else"; 3]);
+}
+
+#[test]
+fn name_policy() {
+  let warns = messages("
+do { (set-name-policy (fn (x) (not (string-starts-with? (->string x) \"bad\")))) };
+term bad_term: wff;
+theorem bad_thm (ph: wff): $ ph <-> ph $ = '(iff_refl ph);
+theorem good_thm (ph: wff): $ ph <-> ph $ = '(iff_refl ph);
+do { (set-name-policy #undef) };
+theorem bad_unchecked (ph: wff): $ ph <-> ph $ = '(iff_refl ph);", ErrorLevel::Warning);
+  assert_eq!(warns, vec![
+    "'bad_term' does not satisfy the naming policy",
+    "'bad_thm' does not satisfy the naming policy"]);
+}
+
+#[test]
+fn with_namespace_prefix() {
+  check("
+do {
+  (with-namespace \"ns_\" (fn () (add-thm! 'inside '([ph wff ()]) () '(iff ph ph))))
+  (add-thm! 'outside '([ph wff ()]) () '(iff ph ph))
+  (if (def? (declared? 'ns_inside)) #undef (error \"the namespace was not applied\"))
+  (if (def? (declared? 'inside)) (error \"the unprefixed name was added\"))
+  (if (def? (declared? 'outside)) #undef (error \"the theorem outside was not added\"))
+  (if (def? (declared? 'ns_outside)) (error \"the namespace outlived with-namespace\"))
+};")
+}
+
+#[test]
+fn open_namespace_resolution() {
+  check("
+do {
+  (with-namespace \"ns_\" (fn () (add-thm! 'refl '([ph wff ()]) () '(iff ph ph))))
+  (def ns_answer 42)
+  (if {(open-namespace \"ns_\" (fn () answer)) = 42} #undef (error \"answer did not resolve\"))
+  (if (def? (try (fn () answer))) (error \"answer resolved outside the namespace\"))
+};
+theorem open_test (ph: wff): $ ph <-> ph $ = (focus
+  (open-namespace \"ns_\" (fn () (refine '(refl ph)))));
+theorem closed_test (ph: wff): $ ph <-> ph $ = (focus
+  (if (def? (try (fn () (refine '(refl ph)) 'ok))) (error \"refl resolved outside the namespace\"))
+  '(ns_refl ph));")
+}
+
+#[test]
+fn thms_by_head_index() {
+  check("
+do {
+  (if {(thms-by-head 'iff) == '(iff_refl iff_symm iff_trans)} #undef (error \"wrong iff theorems\"))
+  (if {(thms-by-head 'im) == '(ax_1)} #undef (error \"wrong im theorems\"))
+  (with-env-snapshot (fn ()
+    (add-thm! 'snap_iff '([ph wff ()]) () '(iff ph ph))
+    (if {(thms-by-head 'iff) == '(iff_refl iff_symm iff_trans snap_iff)} #undef
+      (error \"snap_iff was not indexed\"))
+    'rollback))
+  (if {(thms-by-head 'iff) == '(iff_refl iff_symm iff_trans)} #undef
+    (error \"snap_iff survived the rollback\"))
+  (add-thm! 'new_iff '([ph wff ()]) () '(iff ph ph))
+  (if {(thms-by-head 'iff) == '(iff_refl iff_symm iff_trans new_iff)} #undef
+    (error \"new_iff was not indexed after the rollback\"))
+};")
+}
+
+#[test]
+fn unused_defs() {
+  check("
+def used_def (ph: wff): wff = $ ph <-> ph $;
+def unused_def (ph: wff): wff = $ ph -> ph $;
+local def local_def (ph: wff): wff = $ ~ph $;
+axiom use_def (ph: wff): $ used_def ph $;
+do {
+  (if {(unused-defs) == '(unused_def)} #undef (error \"unused-defs flagged the wrong defs\"))
+};")
+}
+
+#[test]
+fn audit_deps() {
+  check("
+sort set;
+term eqs (x y: set): wff;
+def good {x: set}: wff x = $ eqs x x $;
+do {
+  (if (null? (audit-deps)) #undef (error \"audit-deps flagged a checked def\"))
+  (add-term! 'bad '([x set]) 'wff () () '(eqs x x))
+  (if {(audit-deps) == '(bad)} #undef (error \"audit-deps missed the corrupted def\"))
+};")
+}
+
+#[test]
+fn string_empty_needle() {
+  check("
+do {
+  (if (and (string-contains? \"abc\" \"\") (string-starts-with? \"abc\" \"\")
+        (string-ends-with? \"abc\" \"\") (string-contains? \"\" \"\")) #undef
+    (error \"an empty needle should always match\"))
+};")
+}
+
+#[test]
+fn string_case_conversion() {
+  check("
+do {
+  (if {(string-upcase \"straße\") == \"STRASSE\"} #undef (error \"string-upcase failed on ß\"))
+  (if {(string-downcase \"ÄbC\") == \"äbc\"} #undef (error \"string-downcase failed\"))
+};")
+}
+
+#[test]
+fn abs_sign() {
+  check("
+do {
+  (def big 123456789012345678901234567890)
+  (if (and {(abs 0) = 0} {(sign 0) = 0}) #undef (error \"abs/sign failed on zero\"))
+  (if (and {(abs big) = big} {(abs (- big)) = big}) #undef (error \"abs failed on a large number\"))
+  (if (and {(sign big) = 1} {(sign (- big)) = (- 1)}) #undef (error \"sign failed on a large number\"))
+};")
+}
+
+#[test]
+fn map_remove() {
+  check("
+do {
+  (def m (atom-map! '[a 1] '[b 2]))
+  (if {(map-remove! m 'a) = 1} #undef (error \"map-remove! did not return the old value\"))
+  (if (def? (lookup m 'a)) (error \"map-remove! did not remove the key\"))
+  (if (def? (map-remove! m 'a)) (error \"map-remove! of a missing key returned a value\"))
+  (if {(lookup m 'b) = 2} #undef (error \"map-remove! removed the wrong key\"))
+};")
+}
+
+#[test]
+fn const_precedence_conflict() {
+  let src = format!("{}{}", PRELUDE, "
+term foo (ph: wff): wff; prefix foo: $!$ prec 10;
+term bar (ph: wff): wff; prefix bar: $!$ prec 20;");
+  let first = src.find("$!$").unwrap() + 1;
+  let second = src.rfind("$!$").unwrap() + 1;
+  let (errs, _) = elab(&src);
+  let err = errs.iter().find(|e| e.level == ErrorLevel::Error).expect("no conflict reported");
+  assert_eq!(err.pos.start, second);
+  assert_eq!(err.kind.msg(), "constant '!' declared with precedence 20, \
+    but it was already declared with precedence 10");
+  match &err.kind {
+    super::ElabErrorKind::Boxed(_, Some(info)) => {
+      assert_eq!(info.len(), 1);
+      assert_eq!(info[0].0.span.start, first);
+      assert_eq!(info[0].1.to_string(), "declared with precedence 10 here");
+    }
+    _ => panic!("the earlier declaration was not reported"),
+  }
+}
+
+#[test]
+fn mk_cong_round_trip() {
+  check("
+def idw (ph: wff): wff = $ ph $;
+do {
+  (def c (mk-cong 'iff (nth 1 (unfold! 'idw 'ph)) (mk-refl 'ph)))
+  (if {c == '(:cong iff (:unfold idw (ph) ph) (:refl ph))} #undef (error \"mk-cong built the wrong term\"))
+  (if {(conv-sides c) == '((iff (idw ph) ph) (iff ph ph))} #undef (error \"wrong conversion sides\"))
+  (if (def? (try (fn () (mk-cong 'iff c)))) (error \"mk-cong accepted the wrong arity\"))
+  (if (def? (try (fn () (mk-cong 'iff_refl c c)))) (error \"mk-cong accepted a theorem\"))
+};
+theorem cong_test (ph: wff): $ idw ph <-> ph $ = (focus
+  (refine (list ':verb (list ':conv $ idw ph <-> ph $
+    (mk-cong 'iff (nth 1 (unfold! 'idw 'ph)) (mk-refl 'ph))
+    '(iff_refl ph)))));
+do {
+  (if {(nth 1 (nth 6 (get-decl 'cong_test #t))) ==
+       '(:conv (iff (idw ph) ph) (iff (:unfold idw (ph) ph) ph) (iff_refl ph))} #undef
+    (error \"the cong proof did not round-trip\"))
+};")
+}
+
+#[test]
+fn import_env() {
+  let dir = std::env::temp_dir().join("mm0-rs-import-env");
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(dir.join("dep.mm1"), "").unwrap();
+  let (errs, dep) = elab(&format!("{}axiom dep_ax (ph: wff): $ ph <-> ph $;", PRELUDE));
+  assert!(errs.is_empty());
+  let text = Arc::new(LinedString::from("
+import \"dep.mm1\";
+do {
+  (import-env \"dep.mm1\")
+  (if (def? (declared? 'dep_ax)) #undef (error \"dep_ax was not imported\"))
+  (if (def? (try (fn () (import-env \"missing.mm1\") 'ok)))
+    (error \"import-env accepted a file that was not imported\"))
+};".to_owned()));
+  let (_, ast) = crate::parser::parse(text, None);
+  let mut dep = Some(dep);
+  let (_, errs, _) = futures::executor::block_on(elaborate(
+    Arc::new(ast), dir.join("test.mm1").into(), false, Arc::new(AtomicBool::new(false)), None,
+    |_| -> StdResult<Receiver<((), FrozenEnv)>, BoxError> {
+      let (send, recv) = futures::channel::oneshot::channel();
+      let _ = send.send(((), dep.take().ok_or("imported twice")?));
+      Ok(recv)
+    }));
+  let errs: Vec<_> = errs.into_iter().map(|e| e.kind.msg()).collect();
+  assert!(errs.is_empty(), "elaboration failed:\n{}", errs.join("\n"))
+}
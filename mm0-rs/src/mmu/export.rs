@@ -227,148 +227,155 @@ impl FrozenEnv {
   /// Write this environment into an `mmu` file.
   pub fn export_mmu(&self, mut w: impl Write) -> io::Result<()> {
     let w = &mut w;
-    for &s in self.stmts() {
-      match s {
-        StmtTrace::Sort(a) => {
-          let ad = &self.data()[a];
-          let mods = self.sort(ad.sort().unwrap()).mods;
-          write!(w, "(sort {}", ad.name())?;
-          if mods.contains(Modifiers::PURE) {write!(w, " pure")?}
-          if mods.contains(Modifiers::STRICT) {write!(w, " strict")?}
-          if mods.contains(Modifiers::PROVABLE) {write!(w, " provable")?}
-          if mods.contains(Modifiers::FREE) {write!(w, " free")?}
-          writeln!(w, ")\n")?;
-        }
-        StmtTrace::Decl(a) => {
-          let ad = &self.data()[a];
-          match ad.decl().unwrap() {
-            DeclKey::Term(tid) => {
-              let td = self.term(tid);
-              write!(w, "({}{} {} ",
-                if td.vis == Modifiers::LOCAL {"local "} else {""},
-                if td.val.is_some() {"def"} else {"term"}, ad.name())?;
-              let bvs = self.write_binders(w, &td.args)?;
-              write!(w, " ({} ", &self.sort(td.ret.0).name)?;
-              self.write_deps(w, &bvs, td.ret.1)?;
-              write!(w, ")")?;
-              if let Some(Some(Expr {heap, head})) = &td.val {
-                let mut dummies = HashMap::new();
-                let mut strs: Vec<Vec<u8>> = td.args.iter().map(|&(a, _)|
-                  a.map_or(vec![], |a| Vec::from(self.data()[a].name().as_bytes()))).collect();
-                for e in &heap[td.args.len()..] {
-                  let c = self.write_expr_node(&mut dummies, &strs, e);
-                  strs.push(c);
-                }
-                let ret = self.write_expr_node(&mut dummies, &strs, head);
-                writeln!(w)?;
-                let mut dummies = dummies.into_iter().collect::<Vec<_>>();
-                dummies.sort_by_key(|&(a, _)| &**self.data()[a].name());
-                list(w, dummies.into_iter(), |w, (a, s)|
-                  write!(w, "({} {})", self.data()[a].name(), self.sort(s).name))?;
-                writeln!(w)?;
-                w.write_all(&ret)?;
-              }
-              writeln!(w, ")\n")?;
-            }
-            DeclKey::Thm(tid) => {
-              let td = self.thm(tid);
-              write!(w, "({}{} {} ",
-                if td.vis == Modifiers::PUB || td.proof.is_none() {""} else {"local "},
-                if td.proof.is_some() {"theorem"} else {"axiom"}, ad.name())?;
-              self.write_binders(w, &td.args)?;
+    for &s in self.stmts() { self.export_mmu_stmt(s, w)? }
+    Ok(())
+  }
+
+  /// Write a single statement to `w` in MMU format. Lisp globals produce no output.
+  /// Shared subproofs are written using `(:let H proof body)`. This fails if the
+  /// statement is a theorem whose proof failed to check.
+  pub fn export_mmu_stmt(&self, s: StmtTrace, w: &mut impl Write) -> io::Result<()> {
+    match s {
+      StmtTrace::Sort(a) => {
+        let ad = &self.data()[a];
+        let mods = self.sort(ad.sort().unwrap()).mods;
+        write!(w, "(sort {}", ad.name())?;
+        if mods.contains(Modifiers::PURE) {write!(w, " pure")?}
+        if mods.contains(Modifiers::STRICT) {write!(w, " strict")?}
+        if mods.contains(Modifiers::PROVABLE) {write!(w, " provable")?}
+        if mods.contains(Modifiers::FREE) {write!(w, " free")?}
+        writeln!(w, ")\n")?;
+      }
+      StmtTrace::Decl(a) => {
+        let ad = &self.data()[a];
+        match ad.decl().unwrap() {
+          DeclKey::Term(tid) => {
+            let td = self.term(tid);
+            write!(w, "({}{} {} ",
+              if td.vis == Modifiers::LOCAL {"local "} else {""},
+              if td.val.is_some() {"def"} else {"term"}, ad.name())?;
+            let bvs = self.write_binders(w, &td.args)?;
+            write!(w, " ({} ", &self.sort(td.ret.0).name)?;
+            self.write_deps(w, &bvs, td.ret.1)?;
+            write!(w, ")")?;
+            if let Some(Some(Expr {heap, head})) = &td.val {
               let mut dummies = HashMap::new();
               let mut strs: Vec<Vec<u8>> = td.args.iter().map(|&(a, _)|
                 a.map_or(vec![], |a| Vec::from(self.data()[a].name().as_bytes()))).collect();
-              for e in &td.heap[td.args.len()..] {
+              for e in &heap[td.args.len()..] {
                 let c = self.write_expr_node(&mut dummies, &strs, e);
                 strs.push(c);
               }
-              {
-                let mut it = td.hyps.iter();
-                match it.next() {
-                  None => write!(w, " ()")?,
-                  Some((hyp, typ)) => if td.proof.is_some() {
-                    write!(w, "\n  (({} ", hyp.map_or("_", |a| &self.data()[a].name()))?;
+              let ret = self.write_expr_node(&mut dummies, &strs, head);
+              writeln!(w)?;
+              let mut dummies = dummies.into_iter().collect::<Vec<_>>();
+              dummies.sort_by_key(|&(a, _)| &**self.data()[a].name());
+              list(w, dummies.into_iter(), |w, (a, s)|
+                write!(w, "({} {})", self.data()[a].name(), self.sort(s).name))?;
+              writeln!(w)?;
+              w.write_all(&ret)?;
+            }
+            writeln!(w, ")\n")?;
+          }
+          DeclKey::Thm(tid) => {
+            let td = self.thm(tid);
+            write!(w, "({}{} {} ",
+              if td.vis == Modifiers::PUB || td.proof.is_none() {""} else {"local "},
+              if td.proof.is_some() {"theorem"} else {"axiom"}, ad.name())?;
+            self.write_binders(w, &td.args)?;
+            let mut dummies = HashMap::new();
+            let mut strs: Vec<Vec<u8>> = td.args.iter().map(|&(a, _)|
+              a.map_or(vec![], |a| Vec::from(self.data()[a].name().as_bytes()))).collect();
+            for e in &td.heap[td.args.len()..] {
+              let c = self.write_expr_node(&mut dummies, &strs, e);
+              strs.push(c);
+            }
+            {
+              let mut it = td.hyps.iter();
+              match it.next() {
+                None => write!(w, " ()")?,
+                Some((hyp, typ)) => if td.proof.is_some() {
+                  write!(w, "\n  (({} ", hyp.map_or("_", |a| &self.data()[a].name()))?;
+                  w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
+                  write!(w, ")")?;
+                  for (hyp, typ) in it {
+                    write!(w, "\n   ({} ", hyp.map_or("_", |a| &self.data()[a].name()))?;
                     w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
                     write!(w, ")")?;
-                    for (hyp, typ) in it {
-                      write!(w, "\n   ({} ", hyp.map_or("_", |a| &self.data()[a].name()))?;
-                      w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
-                      write!(w, ")")?;
-                    }
-                    write!(w, ")")?;
-                  } else {
-                    write!(w, "\n  (")?;
+                  }
+                  write!(w, ")")?;
+                } else {
+                  write!(w, "\n  (")?;
+                  w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
+                  for (_, typ) in it {
+                    write!(w, "\n   ")?;
                     w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
-                    for (_, typ) in it {
-                      write!(w, "\n   ")?;
-                      w.write_all(&self.write_expr_node(&mut dummies, &strs, typ))?;
-                    }
-                    write!(w, ")")?;
                   }
+                  write!(w, ")")?;
                 }
               }
-              write!(w, "\n  ")?;
-              w.write_all(&self.write_expr_node(&mut dummies, &strs, &td.ret))?;
-              match &td.proof {
-                None => {},
-                Some(None) => panic!("proof {} missing", self.data()[td.atom].name()),
-                Some(Some(Proof {heap, head, ..})) => {
-                  writeln!(w)?;
-                  let mut idx = 1;
-                  for a in td.args.iter().filter_map(|&(a, _)| a)
-                    .chain(td.hyps.iter().filter_map(|&(a, _)| a))
-                    .chain(dummies.iter().map(|(&a, _)| a)) {
-                    let mut s = self.data()[a].name().chars();
-                    if let Some('H') = s.next() {
-                      if let Ok(n) = s.as_str().parse::<u32>() {idx = n+1}
-                    }
+            }
+            write!(w, "\n  ")?;
+            w.write_all(&self.write_expr_node(&mut dummies, &strs, &td.ret))?;
+            match &td.proof {
+              None => {},
+              Some(None) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("proof {} missing", self.data()[td.atom].name()))),
+              Some(Some(Proof {heap, head, ..})) => {
+                writeln!(w)?;
+                let mut idx = 1;
+                for a in td.args.iter().filter_map(|&(a, _)| a)
+                  .chain(td.hyps.iter().filter_map(|&(a, _)| a))
+                  .chain(dummies.iter().map(|(&a, _)| a)) {
+                  let mut s = self.data()[a].name().chars();
+                  if let Some('H') = s.next() {
+                    if let Ok(n) = s.as_str().parse::<u32>() {idx = n+1}
                   }
-                  let mut lets_start = vec![];
-                  let mut lets_end = vec![];
-                  let mut strs: Vec<_> = strs.into_iter().take(td.args.len())
-                    .map(|v| (vec![], (0, v))).collect();
-                  fn write_lines(w: &mut impl Write, (mut ls, nv): (Vec<Line>, Line)) -> io::Result<()> {
-                    ls.push(nv);
-                    let mut first = true;
-                    for (n, v) in ls {
-                      if !mem::replace(&mut first, false) {
-                        w.write_all(b"\n")?;
-                        w.write_all(&vec![b' '; 2 * n])?
-                      }
-                      w.write_all(&v)?;
+                }
+                let mut lets_start = vec![];
+                let mut lets_end = vec![];
+                let mut strs: Vec<_> = strs.into_iter().take(td.args.len())
+                  .map(|v| (vec![], (0, v))).collect();
+                fn write_lines(w: &mut impl Write, (mut ls, nv): (Vec<Line>, Line)) -> io::Result<()> {
+                  ls.push(nv);
+                  let mut first = true;
+                  for (n, v) in ls {
+                    if !mem::replace(&mut first, false) {
+                      w.write_all(b"\n")?;
+                      w.write_all(&vec![b' '; 2 * n])?
                     }
-                    Ok(())
+                    w.write_all(&v)?;
                   }
-                  for e in &heap[strs.len()..] {
-                    let mut c = self.write_proof_node(&mut dummies, &td.hyps, heap, &strs, e, 0);
-                    if is_nonatomic_proof(e) {
-                      write!(lets_start, "(:let H{} ", idx).unwrap();
-                      write_lines(&mut lets_start, c).unwrap();
-                      lets_start.push(b'\n');
-                      lets_end.push(b')');
-                      c = (vec![], (0, format!("H{}", idx).into_bytes()));
-                      idx += 1
-                    }
-                    strs.push(c);
+                  Ok(())
+                }
+                for e in &heap[strs.len()..] {
+                  let mut c = self.write_proof_node(&mut dummies, &td.hyps, heap, &strs, e, 0);
+                  if is_nonatomic_proof(e) {
+                    write!(lets_start, "(:let H{} ", idx).unwrap();
+                    write_lines(&mut lets_start, c).unwrap();
+                    lets_start.push(b'\n');
+                    lets_end.push(b')');
+                    c = (vec![], (0, format!("H{}", idx).into_bytes()));
+                    idx += 1
                   }
-                  let pf = self.write_proof_node(&mut dummies, &td.hyps, heap, &strs, head, 0);
-                  let mut dummies = dummies.into_iter().collect::<Vec<_>>();
-                  dummies.sort_by_key(|&(a, _)| &**self.data()[a].name());
-                  list(w, dummies.into_iter(), |w, (a, s)|
-                    write!(w, "({} {})", self.data()[a].name(), self.sort(s).name))?;
-                  writeln!(w)?;
-                  w.write_all(&lets_start)?;
-                  write_lines(w, pf)?;
-                  w.write_all(&lets_end)?;
+                  strs.push(c);
                 }
+                let pf = self.write_proof_node(&mut dummies, &td.hyps, heap, &strs, head, 0);
+                let mut dummies = dummies.into_iter().collect::<Vec<_>>();
+                dummies.sort_by_key(|&(a, _)| &**self.data()[a].name());
+                list(w, dummies.into_iter(), |w, (a, s)|
+                  write!(w, "({} {})", self.data()[a].name(), self.sort(s).name))?;
+                writeln!(w)?;
+                w.write_all(&lets_start)?;
+                write_lines(w, pf)?;
+                w.write_all(&lets_end)?;
               }
-              writeln!(w, ")\n")?;
             }
+            writeln!(w, ")\n")?;
           }
         }
-        StmtTrace::Global(_) => {}
       }
+      StmtTrace::Global(_) => {}
     }
     Ok(())
   }
@@ -2,7 +2,7 @@
 use std::rc::Rc;
 use std::collections::{HashMap, hash_map::Entry};
 use crate::elab::{ElabError, Result,
-  local_context::MAX_BOUND_VARS,
+  local_context::{MAX_BOUND_VARS, too_many_bound_vars},
   environment::{Term, Thm, AtomID, SortID, Environment, Modifiers, Type, Expr, Proof},
   proof::{IDedup, NodeHash, ExprHash, ProofHash, build}};
 use crate::util::{Span, BoxError, FileRef, FileSpan};
@@ -277,8 +277,7 @@ impl<'a> Importer<'a> {
         .ok_or_else(|| self.err("expecting sort".into()))?;
       if self.close().is_some() {
         if next_bv >= 1 << MAX_BOUND_VARS {
-          return Err(ElabError::new_e(ysp,
-            format!("too many bound variables (max {})", MAX_BOUND_VARS)))
+          return Err(too_many_bound_vars(&self.env, ysp, oy))
         }
         if y != AtomID::UNDER {bvs.insert(y, next_bv);}
         next_bv *= 2;
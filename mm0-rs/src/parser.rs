@@ -31,7 +31,7 @@ use ast::*;
 ///
 /// [`DiagnosticSeverity`]: ../../lsp_types/enum.DiagnosticSeverity.html
 /// [`to_diag_severity`]: enum.ErrorLevel.html#method.to_diag_severity
-#[derive(Copy, Clone, Debug, DeepSizeOf)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, DeepSizeOf)]
 pub enum ErrorLevel {
   /// Error level for informational messages, such as the result of `(display)`.
   Info,
@@ -539,16 +539,19 @@ async fn hover(path: FileRef, pos: Position) -> StdResult<Option<Hover>, Respons
             }
             let mut subst = Subst::new(&env, &td.heap, args);
             let mut out = String::new();
-            let ret = subst.subst(&td.ret);
-            fe.pretty(|p| p.hyps_and_ret(Pretty::nil(),
-              td.hyps.iter().map(|(_, h)| subst.subst(h)),
-              &ret).render_fmt(80, &mut out).unwrap());
+            let ret = subst.subst(&td.ret).ok()?;
+            let hyps = td.hyps.iter().map(|(_, h)| subst.subst(h))
+              .collect::<StdResult<Vec<_>, _>>().ok()?;
+            fe.pretty(|p| p.hyps_and_ret(Pretty::nil(), hyps.into_iter(), &ret)
+              .render_fmt(80, &mut out).unwrap());
             (sp1, out)
           } else {return None}
         }
       }
+      ObjectKind::Custom(s) => (sp, s.to_string()),
       ObjectKind::Global(_) |
-      ObjectKind::Import(_) => return None,
+      ObjectKind::Import(_) |
+      ObjectKind::Goto(_) => return None,
     }))() {res.push(r)}
   }
   if res.is_empty() {return Ok(None)}
@@ -598,7 +601,7 @@ async fn definition<T>(path: FileRef, pos: Position,
       &ObjectKind::Sort(s) => res.push(sort(s)),
       &ObjectKind::Term(t, _) => res.push(term(t)),
       &ObjectKind::Thm(t) => res.push(thm(t)),
-      ObjectKind::Var(_) => {}
+      ObjectKind::Var(_) | ObjectKind::Custom(_) => {}
       ObjectKind::Expr(e) => {
         let head = e.uncons().next().unwrap_or(e);
         if let Some(DeclKey::Term(t)) = head.as_atom().and_then(|a| env.data()[a].decl()) {
@@ -627,6 +630,7 @@ async fn definition<T>(path: FileRef, pos: Position,
       ObjectKind::Import(file) => {
         res.push(g(&FileSpan {file: file.clone(), span: 0.into()}, 0.into()))
       },
+      ObjectKind::Goto(fsp) => res.push(g(fsp, fsp.span)),
     }
   }
   Ok(res)
@@ -683,11 +687,13 @@ async fn document_symbol(path: FileRef) -> StdResult<DocumentSymbolResponse, Res
               match r {
                 FrozenLispKind::Atom(_) |
                 FrozenLispKind::MVar(_, _) |
+                FrozenLispKind::WeakRef(_) |
                 FrozenLispKind::Goal(_) => SymbolKind::Constant,
                 FrozenLispKind::List(_) |
                 FrozenLispKind::DottedList(_, _) =>
                   if r.is_list() {SymbolKind::Array} else {SymbolKind::Object},
-                FrozenLispKind::Number(_) => SymbolKind::Number,
+                FrozenLispKind::Number(_) |
+                FrozenLispKind::Ratio(_) => SymbolKind::Number,
                 FrozenLispKind::String(_) => SymbolKind::String,
                 FrozenLispKind::Bool(_) => SymbolKind::Boolean,
                 FrozenLispKind::Syntax(_) => SymbolKind::Event,
@@ -736,11 +742,13 @@ fn make_completion_item(path: &FileRef, fe: FormatEnv<'_>, ad: &FrozenAtomData,
       done!(format!("{}", fe.to(unsafe {e.thaw()})), match **e.unwrap() {
         FrozenLispKind::Atom(_) |
         FrozenLispKind::MVar(_, _) |
+        FrozenLispKind::WeakRef(_) |
         FrozenLispKind::Goal(_) => CompletionItemKind::Constant,
         FrozenLispKind::List(_) |
         FrozenLispKind::DottedList(_, _) |
         FrozenLispKind::Undef |
         FrozenLispKind::Number(_) |
+        FrozenLispKind::Ratio(_) |
         FrozenLispKind::String(_) |
         FrozenLispKind::Bool(_) |
         FrozenLispKind::AtomMap(_) => CompletionItemKind::Value,
@@ -824,7 +832,7 @@ async fn references<T>(path: FileRef, pos: Position, include_self: bool, f: impl
         Some(Key::Var(a))
       }
     }
-    ObjectKind::Import(_) => None,
+    ObjectKind::Import(_) | ObjectKind::Custom(_) | ObjectKind::Goto(_) => None,
     ObjectKind::Var(a) => Some(Key::Var(a)),
     ObjectKind::Sort(a) => Some(Key::Sort(a)),
     ObjectKind::Term(a, _) => Some(Key::Term(a)),